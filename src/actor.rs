@@ -0,0 +1,181 @@
+//! An embassy task that owns a DRV8873 and a [Motor].
+//!
+//! Sharing a [DRV8873] between a control task and a fault monitoring task needs a mutex around
+//! the driver. A [DriverTask] instead owns the driver, takes [Command]s from an `embassy_sync`
+//! [Channel] and publishes [Event]s through a [PubSubChannel], so any number of tasks can send
+//! commands and watch the status.
+//!
+//! Embassy tasks can't be generic, so the task function is written for the concrete types:
+//!
+//! ```ignore
+//! static COMMANDS: Channel<CriticalSectionRawMutex, Command, 4> = Channel::new();
+//! static EVENTS: PubSubChannel<CriticalSectionRawMutex, Event, 8, 2, 1> = PubSubChannel::new();
+//!
+//! #[embassy_executor::task]
+//! async fn driver(task: DriverTask<Spi, Output<'static>, Pwm, Output<'static>>) {
+//!     let events = EVENTS.dyn_publisher().unwrap();
+//!     task.run(COMMANDS.dyn_receiver(), events, Delay).await
+//! }
+//! ```
+//!
+//! Events are published with [DynPublisher::publish_immediate], a subscriber that falls behind
+//! loses the oldest events instead of blocking the driver.
+//!
+//! [Channel]: embassy_sync::channel::Channel
+//! [PubSubChannel]: embassy_sync::pubsub::PubSubChannel
+use core::pin::pin;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::channel::DynamicReceiver;
+use embassy_sync::pubsub::DynPublisher;
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::array::DeviceStatus;
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::registers::FaultStatus;
+use crate::{DRV8873Config, Drv8873Error, DRV8873};
+
+/// A request to the [DriverTask].
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Run the motor at a signed Q15 velocity, see [Motor::drive_velocity].
+    SetVelocity(i16),
+    Brake,
+    /// Turn all inputs off, see [Motor::coast].
+    Coast,
+    /// Write a new configuration to the device.
+    ApplyConfig(DRV8873Config),
+    ClearFault,
+    /// Publish the device status right away instead of waiting for the next poll.
+    ReadStatus,
+}
+
+/// Published by the [DriverTask].
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The fault and diagnostic registers, published on every poll and on
+    /// [Command::ReadStatus].
+    Status(DeviceStatus),
+    /// The global fault bit was set.
+    Fault(FaultStatus),
+    /// The global fault bit was cleared.
+    FaultCleared,
+    /// A command or a status poll failed.
+    Error(Drv8873Error),
+}
+
+/// Owns a [DRV8873] and the [Motor] connected to it.
+pub struct DriverTask<D, P, PW, MP, L = ()>
+where
+    D: SpiDevice,
+    P: StatefulOutputPin,
+    PW: SetDutyCycle,
+    MP: StatefulOutputPin,
+    L: FaultSink,
+{
+    drv: DRV8873<D, P, L>,
+    motor: Motor<PW, MP>,
+    poll_us: u32,
+    faulted: bool,
+}
+
+impl<D, P, PW, MP, L> DriverTask<D, P, PW, MP, L>
+where
+    D: SpiDevice,
+    P: StatefulOutputPin,
+    PW: SetDutyCycle,
+    MP: StatefulOutputPin,
+    L: FaultSink,
+{
+    /// Create a task that polls the device status every 10ms.
+    pub fn new(drv: DRV8873<D, P, L>, motor: Motor<PW, MP>) -> Self {
+        Self {
+            drv,
+            motor,
+            poll_us: 10_000,
+            faulted: false,
+        }
+    }
+    /// Set how often the fault and diagnostic registers are read, in microseconds.
+    pub fn with_poll_interval(mut self, poll_us: u32) -> Self {
+        self.poll_us = poll_us;
+        self
+    }
+    /// Release the driver and the motor.
+    pub fn into_inner(self) -> (DRV8873<D, P, L>, Motor<PW, MP>) {
+        (self.drv, self.motor)
+    }
+    /// Execute a single command, `delay` is used to wait for the [ReversalPolicy] of the motor.
+    ///
+    /// [ReversalPolicy]: crate::motor::ReversalPolicy
+    pub async fn handle(
+        &mut self,
+        command: Command,
+        delay: &mut impl DelayNs,
+    ) -> Result<Option<Event>, Drv8873Error> {
+        match command {
+            Command::SetVelocity(velocity) => {
+                self.motor
+                    .drive_velocity(velocity, &mut self.drv, delay)
+                    .await?
+            }
+            Command::Brake => self.motor.brake()?,
+            Command::Coast => self.motor.coast()?,
+            Command::ApplyConfig(cfg) => self.drv.write_config(&cfg).await?,
+            Command::ClearFault => self.drv.clear_fault().await?,
+            Command::ReadStatus => return Ok(Some(Event::Status(self.read_status().await?))),
+        }
+        Ok(None)
+    }
+    async fn read_status(&mut self) -> Result<DeviceStatus, Drv8873Error> {
+        let fault = self.drv.read_fault().await?;
+        let diagnostics = self.drv.read_diagnostics().await?;
+        Ok(DeviceStatus { fault, diagnostics })
+    }
+    /// Read the device status and publish it, together with any change of the fault bit.
+    async fn poll(&mut self, events: &DynPublisher<'_, Event>) {
+        let status = match self.read_status().await {
+            Ok(status) => status,
+            Err(error) => return events.publish_immediate(Event::Error(error)),
+        };
+        let faulted = status.fault.fault();
+        if faulted && !self.faulted {
+            events.publish_immediate(Event::Fault(status.fault));
+        } else if !faulted && self.faulted {
+            events.publish_immediate(Event::FaultCleared);
+        }
+        self.faulted = faulted;
+        events.publish_immediate(Event::Status(status));
+    }
+    /// Handle commands and poll the device status forever. A clone of `delay` waits for the
+    /// [ReversalPolicy] while the original times the polls.
+    ///
+    /// [ReversalPolicy]: crate::motor::ReversalPolicy
+    pub async fn run(
+        mut self,
+        commands: DynamicReceiver<'_, Command>,
+        events: DynPublisher<'_, Event>,
+        mut delay: impl DelayNs + Clone,
+    ) -> ! {
+        let mut reversal_delay = delay.clone();
+        loop {
+            // Commands don't restart the poll interval.
+            let mut tick = pin!(delay.delay_us(self.poll_us));
+            while let Either::First(command) = select(commands.receive(), &mut tick).await {
+                match command {
+                    Command::ReadStatus => self.poll(&events).await,
+                    command => {
+                        if let Err(error) = self.handle(command, &mut reversal_delay).await {
+                            events.publish_immediate(Event::Error(error));
+                        }
+                    }
+                }
+            }
+            self.poll(&events).await;
+        }
+    }
+}
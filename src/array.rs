@@ -0,0 +1,99 @@
+//! Management of several DRV8873 devices sharing an SPI bus.
+//!
+//! When multiple devices share a bus, their nFAULT outputs are usually wired together. A
+//! [DRV8873Array] owns all the drivers, applies the same [DRV8873Config] to each of them and reads
+//! back their status to find out which device pulled the shared fault line.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::registers::{DiagnosticStatus, FaultStatus};
+use crate::{DRV8873Config, Drv8873Error, DRV8873};
+
+/// An error of a single device in a [DRV8873Array].
+#[derive(core::fmt::Debug)]
+pub struct ArrayError {
+    /// Index of the device in the array.
+    pub index: usize,
+    pub error: Drv8873Error,
+}
+
+/// Fault and diagnostic registers of a single device.
+#[derive(core::fmt::Debug, Clone, Copy)]
+pub struct DeviceStatus {
+    pub fault: FaultStatus,
+    pub diagnostics: DiagnosticStatus,
+}
+
+/// A fixed number of [DRV8873] devices that are managed as a group.
+pub struct DRV8873Array<D: SpiDevice, P: StatefulOutputPin, const N: usize, L: FaultSink = ()> {
+    devices: [DRV8873<D, P, L>; N],
+}
+
+impl<D: SpiDevice, P: StatefulOutputPin, const N: usize, L: FaultSink> DRV8873Array<D, P, N, L> {
+    pub fn new(devices: [DRV8873<D, P, L>; N]) -> Self {
+        Self { devices }
+    }
+    /// Get a single device from the array.
+    pub fn device(&mut self, index: usize) -> Option<&mut DRV8873<D, P, L>> {
+        self.devices.get_mut(index)
+    }
+    /// Iterate over all the devices in the array.
+    pub fn devices(&mut self) -> impl Iterator<Item = &mut DRV8873<D, P, L>> {
+        self.devices.iter_mut()
+    }
+    /// Release the devices.
+    pub fn into_inner(self) -> [DRV8873<D, P, L>; N] {
+        self.devices
+    }
+    /// Write the same [DRV8873Config] to every device, stops at the first device that fails.
+    pub async fn write_config(&mut self, cfg: &DRV8873Config) -> Result<(), ArrayError> {
+        for (index, dev) in self.devices.iter_mut().enumerate() {
+            dev.write_config(cfg)
+                .await
+                .map_err(|error| ArrayError { index, error })?;
+        }
+        Ok(())
+    }
+    /// Read the [FaultStatus] register of every device.
+    pub async fn read_faults(&mut self) -> [Result<FaultStatus, Drv8873Error>; N] {
+        let mut faults = core::array::from_fn(|_| Err(Drv8873Error::SpiError()));
+        for (fault, dev) in faults.iter_mut().zip(self.devices.iter_mut()) {
+            *fault = dev.read_fault().await;
+        }
+        faults
+    }
+    /// Read the [FaultStatus] and [DiagnosticStatus] registers of every device.
+    pub async fn read_status(&mut self) -> [Result<DeviceStatus, Drv8873Error>; N] {
+        let mut status = core::array::from_fn(|_| Err(Drv8873Error::SpiError()));
+        for (status, dev) in status.iter_mut().zip(self.devices.iter_mut()) {
+            *status = match (dev.read_fault().await, dev.read_diagnostics().await) {
+                (Ok(fault), Ok(diagnostics)) => Ok(DeviceStatus { fault, diagnostics }),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+        }
+        status
+    }
+    /// Find the devices that are asserting the shared nFAULT line, an element is `true` when the
+    /// global fault bit of that device is set.
+    pub async fn faulted(&mut self) -> Result<[bool; N], ArrayError> {
+        let mut faulted = [false; N];
+        for (index, dev) in self.devices.iter_mut().enumerate() {
+            faulted[index] = dev
+                .read_fault()
+                .await
+                .map_err(|error| ArrayError { index, error })?
+                .fault();
+        }
+        Ok(faulted)
+    }
+    /// Clear the faults of every device, stops at the first device that fails.
+    pub async fn clear_faults(&mut self) -> Result<(), ArrayError> {
+        for (index, dev) in self.devices.iter_mut().enumerate() {
+            dev.clear_fault()
+                .await
+                .map_err(|error| ArrayError { index, error })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! # drv8873-decode
+//!
+//! Decodes captured DRV8873 SPI frames into commands, status bytes and register fields.
+//!
+//! Frames are given as hex on the command line, two MOSI bytes optionally followed by `/` and the
+//! two MISO bytes:
+//!
+//! ```text
+//! drv8873-decode 4200/c080 0482
+//! ```
+//!
+//! Alternatively `--csv <file>` reads a logic analyser export with one byte per row. The `MOSI`
+//! and `MISO` columns are located through the header and consecutive rows are paired into frames.
+use std::process::ExitCode;
+
+use drv8873::capture::{parse_csv, parse_frame, Frame};
+use drv8873::registers::{
+    ControlRegister1, ControlRegister2, ControlRegister3, ControlRegister4, DiagnosticStatus,
+    FaultStatus,
+};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let frames = match args.as_slice() {
+        [] => {
+            eprintln!("usage: drv8873-decode <CCDD[/SSRR]>... | --csv <file>");
+            return ExitCode::FAILURE;
+        }
+        [flag, path] if flag == "--csv" => std::fs::read_to_string(path)
+            .map_err(|e| format!("{path}: {e}"))
+            .and_then(|csv| parse_csv(&csv)),
+        frames => frames.iter().map(|f| parse_frame(f)).collect(),
+    };
+    match frames {
+        Ok(frames) => {
+            for (i, frame) in frames.iter().enumerate() {
+                println!("frame {i}:");
+                decode(frame);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn register_name(addr: u8) -> &'static str {
+    match addr {
+        0x00 => "FaultStatus",
+        0x01 => "DiagnosticStatus",
+        0x02 => "ControlRegister1",
+        0x03 => "ControlRegister2",
+        0x04 => "ControlRegister3",
+        0x05 => "ControlRegister4",
+        _ => "unknown register",
+    }
+}
+
+fn print_register(addr: u8, byte: u8) {
+    match addr {
+        0x00 => println!("    {:?}", FaultStatus(byte)),
+        0x01 => println!("    {:?}", DiagnosticStatus(byte)),
+        0x02 => println!("    {:?}", ControlRegister1(byte)),
+        0x03 => println!("    {:?}", ControlRegister2(byte)),
+        0x04 => println!("    {:?}", ControlRegister3(byte)),
+        0x05 => println!("    {:?}", ControlRegister4(byte)),
+        _ => println!("    {byte:#010b}"),
+    }
+}
+
+fn decode(frame: &Frame) {
+    let command = frame.command();
+    let addr = command.address;
+    let direction = if command.read { "read" } else { "write" };
+    println!(
+        "  command {:#04x}: {direction} {addr:#04x} ({})",
+        frame.mosi[0],
+        register_name(addr)
+    );
+    if command.reserved_bits {
+        println!("  warning: reserved command bits are set");
+    }
+    if !command.read {
+        println!("  data {:#04x}:", frame.mosi[1]);
+        print_register(addr, frame.mosi[1]);
+    }
+
+    let (Some([status_byte, data]), Some(status)) = (frame.miso, frame.status()) else {
+        return;
+    };
+    if !status.framing_ok {
+        println!(
+            "  status {status_byte:#04x}: invalid framing, the two most significant bits must be set"
+        );
+    }
+    match status.fault {
+        Some(fault) => println!("  status {status_byte:#04x}: {fault:?}"),
+        None => println!("  status {status_byte:#04x}: no faults"),
+    }
+    if command.read {
+        println!("  response {data:#04x}:");
+        print_register(addr, data);
+    }
+}
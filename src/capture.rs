@@ -0,0 +1,125 @@
+//! Parsing and decoding of captured SPI frames, used by the `drv8873-decode` tool.
+//!
+//! Frames are written as hex, two MOSI bytes optionally followed by `/` and the two MISO bytes,
+//! e.g. `4200/c080`. [parse_csv] reads a logic analyser export with one byte per row instead.
+use std::format;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::registers::{get_status, CommandByte, FaultStatus};
+
+/// A single 16 bit SPI frame, the MISO bytes are optional as not every capture records them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub mosi: [u8; 2],
+    pub miso: Option<[u8; 2]>,
+}
+
+/// The command byte of a [Frame].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    pub read: bool,
+    pub address: u8,
+    /// Bit 7 or bit 0 is set, both must be zero.
+    pub reserved_bits: bool,
+}
+
+/// The status byte the device returned in a [Frame].
+#[derive(Debug, Clone, Copy)]
+pub struct Status {
+    /// The two most significant bits are set.
+    pub framing_ok: bool,
+    /// The fault bits, `None` when no fault is reported.
+    pub fault: Option<FaultStatus>,
+}
+
+impl Frame {
+    pub fn command(&self) -> Command {
+        let cb = CommandByte(self.mosi[0]);
+        Command {
+            read: cb.read_bit(),
+            address: cb.address(),
+            reserved_bits: cb.0 & 0b1000_0001 != 0,
+        }
+    }
+    /// Decode the status byte, `None` when the MISO bytes weren't captured.
+    pub fn status(&self) -> Option<Status> {
+        self.miso.map(|[status, _]| Status {
+            framing_ok: status & 0b1100_0000 == 0b1100_0000,
+            fault: get_status(status),
+        })
+    }
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    let s = s.trim();
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u8::from_str_radix(digits, 16).map_err(|_| format!("invalid hex byte `{s}`"))
+}
+
+fn parse_pair(s: &str) -> Result<[u8; 2], String> {
+    let s = s.trim();
+    if s.len() != 4 || !s.is_ascii() {
+        return Err(format!("expected two hex bytes, found `{s}`"));
+    }
+    Ok([parse_byte(&s[..2])?, parse_byte(&s[2..])?])
+}
+
+/// Parse a frame written as `CCDD` or `CCDD/SSRR`.
+pub fn parse_frame(s: &str) -> Result<Frame, String> {
+    let (mosi, miso) = match s.split_once('/') {
+        Some((mosi, miso)) => (mosi, Some(miso)),
+        None => (s, None),
+    };
+    Ok(Frame {
+        mosi: parse_pair(mosi)?,
+        miso: miso.map(parse_pair).transpose()?,
+    })
+}
+
+/// Parse a CSV export with one byte per row. The `MOSI` and `MISO` columns are located through
+/// the header and consecutive rows are paired into frames.
+pub fn parse_csv(csv: &str) -> Result<Vec<Frame>, String> {
+    let mut lines = csv.lines().filter(|l| !l.trim().is_empty());
+    let header: Vec<String> = lines
+        .next()
+        .ok_or("empty CSV file")?
+        .split(',')
+        .map(|c| c.trim().trim_matches('"').to_ascii_uppercase())
+        .collect();
+    let mosi_col = header
+        .iter()
+        .position(|c| c.contains("MOSI"))
+        .ok_or("no MOSI column in CSV header")?;
+    let miso_col = header.iter().position(|c| c.contains("MISO"));
+
+    let mut bytes = Vec::new();
+    for line in lines {
+        let cols: Vec<&str> = line
+            .split(',')
+            .map(|c| c.trim().trim_matches('"'))
+            .collect();
+        let column = |i: usize| {
+            cols.get(i)
+                .ok_or_else(|| format!("missing column in `{line}`"))
+        };
+        let mosi = parse_byte(column(mosi_col)?)?;
+        let miso = miso_col
+            .map(|i| column(i).and_then(|c| parse_byte(c)))
+            .transpose()?;
+        bytes.push((mosi, miso));
+    }
+    if bytes.len() % 2 != 0 {
+        return Err(format!("odd number of bytes ({}) in capture", bytes.len()));
+    }
+    Ok(bytes
+        .chunks(2)
+        .map(|c| Frame {
+            mosi: [c[0].0, c[1].0],
+            miso: c[0].1.zip(c[1].1).map(|(s, r)| [s, r]),
+        })
+        .collect())
+}
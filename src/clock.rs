@@ -0,0 +1,11 @@
+//! A monotonic time source.
+//!
+//! Timeouts and timestamps that don't run inside a [DelayNs](embedded_hal_async::delay::DelayNs)
+//! loop need to know the current time, for example from a free running timer, the SysTick or
+//! `embassy_time::Instant`.
+
+/// A monotonic clock.
+pub trait Clock {
+    /// Microseconds since an arbitrary starting point, never decreasing.
+    fn now_us(&mut self) -> u64;
+}
@@ -1,14 +1,18 @@
+use core::fmt::Display;
+use core::str::FromStr;
 use defmt::Format;
 use defmt::Formatter;
-use embedded_hal_async::spi::Operation;
 use embedded_hal_async::spi::SpiDevice;
 
-use crate::registers::get_status;
 use crate::{
-    registers::{CommandByte, ReadableRegister, Register},
+    registers::{CommandByte, Lock, ReadableRegister, Register},
     registers::{ControlRegister1, ControlRegister2, ControlRegister3, ControlRegister4},
     Drv8873Error,
 };
+/// Position of the lock field in [ControlRegister3].
+const LOCK_SHIFT: u8 = 4;
+const LOCK_MASK: u8 = 0b111 << LOCK_SHIFT;
+
 /// Holds the 4 control registers of the DRV8873 IC and associated functions to read and write
 /// them. Can be used to initialize a [DRV8873] to something other than default settings.
 #[derive(Debug, Default)]
@@ -30,17 +34,133 @@ impl Clone for DRV8873Config {
 }
 impl Format for DRV8873Config {
     fn format(&self, fmt: Formatter) {
-        defmt::write!(
-            fmt,
-            "{},{},{},{}",
-            self.cr1.0,
-            self.cr2.0,
-            self.cr3.0,
-            self.cr4.0
+        defmt::write!(fmt, "{}", defmt::Display2Format(self))
+    }
+}
+
+/// Formats the configuration as space separated `key=value` pairs, e.g.
+/// `mode=pwm sr=10.8V/us toff=40us ...`. The output can be parsed back with [FromStr].
+///
+/// The self-clearing `clr_flt` and `en_olp` bits are not part of the text format. A lock field
+/// that holds neither [Lock] sequence is written as its raw value, e.g. `lock=2`.
+impl Display for DRV8873Config {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "mode={} sr={} toff={} spi_in={} ",
+            self.cr1.mode(),
+            self.cr1.sr(),
+            self.cr1.toff(),
+            u8::from(self.cr1.spi_in())
+        )?;
+        write!(
+            f,
+            "ocp={} tretry={} dis_cpuv={} otw_rep={} tsd_mode={} itrip_rep={} ",
+            self.cr2.ocp_mode(),
+            self.cr2.ocp_t_retry(),
+            u8::from(self.cr2.dis_cpuv()),
+            u8::from(self.cr2.otw_rep()),
+            u8::from(self.cr2.tsd_mode()),
+            u8::from(self.cr2.itrip_rep())
+        )?;
+        write!(
+            f,
+            "en_in1={} ph_in2={} out1_dis={} out2_dis={} lock=",
+            u8::from(self.cr3.en_in1()),
+            u8::from(self.cr3.ph_in2()),
+            u8::from(self.cr3.out1_dis()),
+            u8::from(self.cr3.out2_dis()),
+        )?;
+        let lock = (self.cr3.0 & LOCK_MASK) >> LOCK_SHIFT;
+        if u8::from(Lock::from(lock)) == lock {
+            write!(f, "{} ", Lock::from(lock))?;
+        } else {
+            write!(f, "{} ", lock)?;
+        }
+        write!(
+            f,
+            "itrip={} itrip_dis={} en_ola={} olp_dly={}",
+            self.cr4.i_trip_lvl(),
+            self.cr4.i_trip(),
+            u8::from(self.cr4.en_ola()),
+            u8::from(self.cr4.olp_dly())
         )
     }
 }
 
+/// Errors returned when parsing a [DRV8873Config] from text.
+#[derive(Debug, PartialEq)]
+pub enum ParseConfigError {
+    /// A token was not of the form `key=value`.
+    MissingValue,
+    /// The key is not one of the keys produced by the [Display] implementation.
+    UnknownKey,
+    /// The value is not valid for the given key.
+    InvalidValue(&'static str),
+}
+
+/// Parses space separated `key=value` pairs as produced by the [Display] implementation.
+///
+/// Keys may appear in any order and can be omitted, omitted keys keep their reset value. When a
+/// key appears more than once the last occurrence wins.
+impl FromStr for DRV8873Config {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn field<T: FromStr>(key: &'static str, value: &str) -> Result<T, ParseConfigError> {
+            value
+                .parse()
+                .map_err(|_| ParseConfigError::InvalidValue(key))
+        }
+        fn flag(key: &'static str, value: &str) -> Result<bool, ParseConfigError> {
+            match value {
+                "0" => Ok(false),
+                "1" => Ok(true),
+                _ => Err(ParseConfigError::InvalidValue(key)),
+            }
+        }
+
+        let mut cfg = Self::default();
+        for token in s.split_ascii_whitespace() {
+            let (key, value) = token
+                .split_once('=')
+                .ok_or(ParseConfigError::MissingValue)?;
+            match key {
+                "mode" => cfg.cr1.set_mode(field("mode", value)?),
+                "sr" => cfg.cr1.set_sr(field("sr", value)?),
+                "toff" => cfg.cr1.set_toff(field("toff", value)?),
+                "spi_in" => cfg.cr1.set_spi_in(flag("spi_in", value)?),
+                "ocp" => cfg.cr2.set_ocp_mode(field("ocp", value)?),
+                "tretry" => cfg.cr2.set_ocp_t_retry(field("tretry", value)?),
+                "dis_cpuv" => cfg.cr2.set_dis_cpuv(flag("dis_cpuv", value)?),
+                "otw_rep" => cfg.cr2.set_otw_rep(flag("otw_rep", value)?),
+                "tsd_mode" => cfg.cr2.set_tsd_mode(flag("tsd_mode", value)?),
+                "itrip_rep" => cfg.cr2.set_itrip_rep(flag("itrip_rep", value)?),
+                "en_in1" => cfg.cr3.set_en_in1(flag("en_in1", value)?),
+                "ph_in2" => cfg.cr3.set_ph_in2(flag("ph_in2", value)?),
+                "out1_dis" => cfg.cr3.set_out1_dis(flag("out1_dis", value)?),
+                "out2_dis" => cfg.cr3.set_out2_dis(flag("out2_dis", value)?),
+                "lock" => {
+                    let lock = field::<Lock>("lock", value).map(u8::from).or_else(|err| {
+                        value
+                            .parse::<u8>()
+                            .ok()
+                            .filter(|raw| *raw <= LOCK_MASK >> LOCK_SHIFT)
+                            .ok_or(err)
+                    })?;
+                    cfg.cr3.0 = cfg.cr3.0 & !LOCK_MASK | lock << LOCK_SHIFT;
+                }
+                "itrip" => cfg.cr4.set_i_trip_lvl(field("itrip", value)?),
+                "itrip_dis" => cfg.cr4.set_i_trip(field("itrip_dis", value)?),
+                "en_ola" => cfg.cr4.set_en_ola(flag("en_ola", value)?),
+                "olp_dly" => cfg.cr4.set_old_dly(flag("olp_dly", value)?),
+                _ => return Err(ParseConfigError::UnknownKey),
+            }
+        }
+        Ok(cfg)
+    }
+}
+
 impl PartialEq for DRV8873Config {
     fn eq(&self, other: &Self) -> bool {
         self.cr1.0 == other.cr1.0
@@ -0,0 +1,169 @@
+//! Closed-loop current (torque) control with IPROPI feedback.
+//!
+//! The IPROPI pin of the DRV8873 sources a current proportional to the load current, a resistor
+//! to ground turns it into a voltage that is measured with an ADC. A [CurrentController] runs a
+//! [Pid] on the difference between the measured current and a setpoint in milliamps and drives a
+//! [Motor]. The hardware ITRIP level is kept at the lowest [ITripLvl] above the setpoint so the
+//! DRV8873 limits the current if the software loop fails to.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::pid::{Pid, PidGains};
+use crate::ramp::FULL_SCALE;
+use crate::registers::{DisITrip, ITripLvl};
+use crate::{Drv8873Error, DRV8873};
+
+/// A source of IPROPI measurements.
+pub trait CurrentSense {
+    /// Measure the voltage on the IPROPI pin in millivolts.
+    fn read_millivolts(&mut self) -> u32;
+}
+
+/// Converts the IPROPI voltage to the load current.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpropiScale {
+    /// Resistance between IPROPI and ground in ohms.
+    pub resistor_ohm: u32,
+    /// Current mirror gain in microamps per amp of load current.
+    pub gain_ua_per_a: u32,
+}
+
+impl Default for IpropiScale {
+    /// The typical datasheet gain of 1100 µA/A with a 1 kΩ resistor.
+    fn default() -> Self {
+        Self {
+            resistor_ohm: 1_000,
+            gain_ua_per_a: 1_100,
+        }
+    }
+}
+
+impl IpropiScale {
+    /// The load current in milliamps for an IPROPI voltage in millivolts.
+    pub fn milliamps(&self, millivolts: u32) -> u32 {
+        let denom = self.resistor_ohm as u64 * self.gain_ua_per_a as u64;
+        if denom == 0 {
+            return 0;
+        }
+        (millivolts as u64 * 1_000_000 / denom).min(u32::MAX as u64) as u32
+    }
+}
+
+/// Current controller for a motor with IPROPI feedback.
+pub struct CurrentController<A: CurrentSense> {
+    adc: A,
+    scale: IpropiScale,
+    pid: Pid,
+    setpoint: i32,
+    current: u32,
+    ceiling: Option<ITripLvl>,
+}
+
+impl<A: CurrentSense> CurrentController<A> {
+    /// Create a controller, the PID output is a Q15 duty and the error is in milliamps.
+    pub fn new(adc: A, scale: IpropiScale, gains: PidGains) -> Self {
+        Self {
+            adc,
+            scale,
+            pid: Pid::new(gains, 0, FULL_SCALE),
+            setpoint: 0,
+            current: 0,
+            ceiling: None,
+        }
+    }
+    /// Release the ADC.
+    pub fn into_inner(self) -> A {
+        self.adc
+    }
+    pub fn pid(&mut self) -> &mut Pid {
+        &mut self.pid
+    }
+    /// The target current in milliamps, negative when driving backward.
+    pub fn setpoint(&self) -> i32 {
+        self.setpoint
+    }
+    /// The ITRIP level last written by [CurrentController::set_setpoint].
+    pub fn ceiling(&self) -> Option<ITripLvl> {
+        self.ceiling
+    }
+    /// Set the target current in milliamps, the sign selects the direction.
+    ///
+    /// ITRIP regulation is enabled on both outputs at the lowest level above the setpoint, the
+    /// register is only written when the level changes. Setpoints at or above the highest level
+    /// are rejected with [Drv8873Error::InputError].
+    pub async fn set_setpoint<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        milliamps: i32,
+    ) -> Result<ITripLvl, Drv8873Error> {
+        let level = ITripLvl::above(milliamps.unsigned_abs()).ok_or(Drv8873Error::InputError(
+            "Current setpoint above the ITRIP range",
+        ))?;
+        if self.ceiling != Some(level) {
+            drv.modify_cr4(|mut cr4| {
+                cr4.set_i_trip(DisITrip::Enabled);
+                cr4.set_i_trip_lvl(level);
+                cr4
+            })
+            .await?;
+            self.ceiling = Some(level);
+        }
+        if milliamps.signum() != self.setpoint.signum() {
+            self.pid.reset();
+        }
+        self.setpoint = milliamps;
+        Ok(level)
+    }
+    /// The last measured load current in milliamps.
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+    /// Clear the controller state, for example after the motor was stopped outside of the loop.
+    pub fn reset(&mut self) {
+        self.pid.reset();
+        self.current = 0;
+    }
+    /// Run the controller on a current measurement taken `dt_us` microseconds after the previous
+    /// one and return the signed Q15 speed to apply.
+    pub fn control(&mut self, milliamps: u32, dt_us: u32) -> i16 {
+        self.current = milliamps;
+        let target = self.setpoint.unsigned_abs() as i64;
+        let error = (target - milliamps as i64).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        let duty = self.pid.update(error, dt_us, false) as i16;
+        if self.setpoint < 0 {
+            -duty
+        } else {
+            duty
+        }
+    }
+    /// Measure IPROPI and update the motor, `dt_us` is the time since the previous update.
+    /// Returns the Q15 speed that was applied.
+    ///
+    /// A change of direction goes through the [ReversalPolicy] of the motor, `delay` is used to
+    /// wait for it.
+    ///
+    /// [ReversalPolicy]: crate::motor::ReversalPolicy
+    pub async fn update<D, P, PW, MP, L>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+        dt_us: u32,
+    ) -> Result<i16, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        let milliamps = self.scale.milliamps(self.adc.read_millivolts());
+        let speed = self.control(milliamps, dt_us);
+        motor.drive_velocity(speed, drv, delay).await?;
+        Ok(speed)
+    }
+}
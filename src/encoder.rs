@@ -0,0 +1,56 @@
+//! Encoder feedback for closed-loop control.
+//!
+//! The closed-loop controllers only need a position count and the time it was captured, so any
+//! quadrature decoder (timer peripheral, interrupt driven or external counter) can be used by
+//! implementing [Encoder].
+
+/// A position count together with the time it was captured.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EncoderSample {
+    /// Position in encoder counts, allowed to wrap around.
+    pub count: i32,
+    /// Capture time in microseconds, allowed to wrap around.
+    pub timestamp_us: u32,
+}
+
+/// A source of position feedback.
+pub trait Encoder {
+    /// Capture the current count.
+    fn sample(&mut self) -> EncoderSample;
+}
+
+/// Derives the speed from successive [EncoderSample]s.
+#[derive(Debug, Clone, Default)]
+pub struct SpeedEstimator {
+    last: Option<EncoderSample>,
+    speed: i32,
+}
+
+impl SpeedEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Forget the previous sample, the next call to [SpeedEstimator::update] only records it.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.speed = 0;
+    }
+    /// The last estimated speed in counts per second.
+    pub fn speed(&self) -> i32 {
+        self.speed
+    }
+    /// Add a sample and return the speed in counts per second since the previous one. Samples
+    /// with the same timestamp as the previous one keep the last estimate.
+    pub fn update(&mut self, sample: EncoderSample) -> i32 {
+        if let Some(last) = self.last {
+            let dt = sample.timestamp_us.wrapping_sub(last.timestamp_us);
+            if dt == 0 {
+                return self.speed;
+            }
+            let counts = sample.count.wrapping_sub(last.count) as i64;
+            self.speed = (counts * 1_000_000 / dt as i64) as i32;
+        }
+        self.last = Some(sample);
+        self.speed
+    }
+}
@@ -0,0 +1,246 @@
+//! Fault history with timestamps.
+//!
+//! A [FaultLog] keeps the last `N` fault and diagnostic events of a device in a fixed capacity
+//! ring buffer, together with the time from a [Clock], the velocity the [Motor] was commanded to
+//! and the [RecoveryAction] taken. When the log is full the oldest record is overwritten.
+//!
+//! The log is attached with [DRV8873::with_fault_log] and sees every status the driver reads,
+//! so the controllers, [DRV8873Array], [DRV8873::init] and [DRV8873::self_test] all feed it.
+//! A new record is added when a fault flag other than FAULT comes up or changes. Current
+//! regulation through ITRIP alone isn't an event, with `itrip_rep` it only toggles the FAULT
+//! bit. Diagnostic bits read while a fault is open are merged into its record. After
+//! [DRV8873::clear_fault] the same flags are recorded again when they recur. A fault that comes
+//! and goes between two reads is missed.
+//!
+//! [FaultLog::export] writes the records in a compact binary format that can be read back with
+//! [FaultRecord::from_bytes], for example after reading them out of a field return.
+//!
+//! [Motor]: crate::motor::Motor
+//! [DRV8873Array]: crate::array::DRV8873Array
+//! [DRV8873]: crate::DRV8873
+//! [DRV8873::with_fault_log]: crate::DRV8873::with_fault_log
+//! [DRV8873::init]: crate::DRV8873::init
+//! [DRV8873::self_test]: crate::DRV8873::self_test
+//! [DRV8873::clear_fault]: crate::DRV8873::clear_fault
+use heapless::Deque;
+use num_enum::{Default, FromPrimitive, IntoPrimitive};
+
+use crate::array::DeviceStatus;
+use crate::clock::Clock;
+use crate::registers::{DiagnosticStatus, FaultStatus};
+
+/// The [FaultStatus] flags from OLD to OTW. FAULT is left out, with `itrip_rep` it's also set by
+/// ITRIP current regulation.
+const EVENT_FLAGS: u8 = 0x3F;
+
+/// Receives every status a [DRV8873] reads, see [DRV8873::with_fault_log].
+///
+/// [DRV8873]: crate::DRV8873
+/// [DRV8873::with_fault_log]: crate::DRV8873::with_fault_log
+pub trait FaultSink {
+    fn fault(&mut self, fault: FaultStatus);
+    fn diagnostics(&mut self, diagnostics: DiagnosticStatus);
+    /// The commanded signed Q15 velocity, forwarded by the [Motor] methods that take the driver.
+    ///
+    /// [Motor]: crate::motor::Motor
+    fn velocity(&mut self, _velocity: i16) {}
+    /// The faults were cleared through `clr_flt`.
+    fn cleared(&mut self) {}
+}
+
+/// No log attached.
+impl FaultSink for () {
+    fn fault(&mut self, _fault: FaultStatus) {}
+    fn diagnostics(&mut self, _diagnostics: DiagnosticStatus) {}
+}
+
+/// What was done in response to a fault.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, IntoPrimitive, Default)]
+pub enum RecoveryAction {
+    #[default]
+    None = 0,
+    /// The faults were cleared through `clr_flt`.
+    ClearFault = 1,
+    /// Both low-side FETs were turned on, see [Motor::brake].
+    ///
+    /// [Motor::brake]: crate::motor::Motor::brake
+    Brake = 2,
+    /// The inputs were turned off.
+    Coast = 3,
+    /// The outputs were disabled through the DISABLE pin.
+    Disable = 4,
+    /// The device was put to sleep through the nSLEEP pin.
+    Sleep = 5,
+}
+
+/// A single entry of a [FaultLog].
+#[derive(Debug, Clone, Copy)]
+pub struct FaultRecord {
+    /// [Clock::now_us] when the event was recorded.
+    pub timestamp_us: u64,
+    pub status: DeviceStatus,
+    /// The commanded signed Q15 velocity, see [Motor::velocity].
+    ///
+    /// [Motor::velocity]: crate::motor::Motor::velocity
+    pub velocity: i16,
+    pub action: RecoveryAction,
+}
+
+impl FaultRecord {
+    /// Length of the binary format in bytes.
+    pub const SIZE: usize = 13;
+
+    /// The timestamp and velocity in little endian, followed by the [FaultStatus],
+    /// [DiagnosticStatus] and [RecoveryAction] bytes.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.velocity.to_le_bytes());
+        bytes[10] = self.status.fault.0;
+        bytes[11] = self.status.diagnostics.0;
+        bytes[12] = self.action.into();
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[..8]);
+        Self {
+            timestamp_us: u64::from_le_bytes(timestamp),
+            velocity: i16::from_le_bytes([bytes[8], bytes[9]]),
+            status: DeviceStatus {
+                fault: FaultStatus(bytes[10]),
+                diagnostics: DiagnosticStatus(bytes[11]),
+            },
+            action: RecoveryAction::from(bytes[12]),
+        }
+    }
+}
+
+/// The last `N` fault events of a device.
+pub struct FaultLog<C: Clock, const N: usize> {
+    clock: C,
+    records: Deque<FaultRecord, N>,
+    /// The fault flags of the last read, without the FAULT bit.
+    last: u8,
+    /// The newest record belongs to a fault that is still reported.
+    open: bool,
+    diagnostics: DiagnosticStatus,
+    velocity: i16,
+    total: u32,
+}
+
+impl<C: Clock, const N: usize> FaultLog<C, N> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            records: Deque::new(),
+            last: 0,
+            open: false,
+            diagnostics: DiagnosticStatus(0),
+            velocity: 0,
+            total: 0,
+        }
+    }
+    /// Release the clock.
+    pub fn into_inner(self) -> C {
+        self.clock
+    }
+    /// Add a record with the current time, overwriting the oldest one when the log is full.
+    pub fn record(
+        &mut self,
+        status: DeviceStatus,
+        velocity: i16,
+        action: RecoveryAction,
+    ) -> &FaultRecord {
+        let record = FaultRecord {
+            timestamp_us: self.clock.now_us(),
+            status,
+            velocity,
+            action,
+        };
+        if self.records.is_full() {
+            self.records.pop_front();
+        }
+        // Can't fail, there's room after removing the oldest record.
+        let _ = self.records.push_back(record);
+        self.total = self.total.saturating_add(1);
+        self.records.back().unwrap()
+    }
+    /// Set the [RecoveryAction] of the newest record, returns false when the log is empty.
+    pub fn set_action(&mut self, action: RecoveryAction) -> bool {
+        self.records
+            .back_mut()
+            .map(|record| record.action = action)
+            .is_some()
+    }
+    /// The records from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &FaultRecord> {
+        self.records.iter()
+    }
+    pub fn latest(&self) -> Option<&FaultRecord> {
+        self.records.back()
+    }
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+    /// Number of records since the log was created or cleared, including overwritten ones.
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.last = 0;
+        self.open = false;
+        self.total = 0;
+    }
+    /// Write as many records as fit into `buf` from oldest to newest, each
+    /// [FaultRecord::SIZE] bytes long. Returns the number of bytes written.
+    pub fn export(&self, buf: &mut [u8]) -> usize {
+        buf.chunks_exact_mut(FaultRecord::SIZE)
+            .zip(self.records.iter())
+            .map(|(chunk, record)| chunk.copy_from_slice(&record.to_bytes()))
+            .count()
+            * FaultRecord::SIZE
+    }
+}
+
+impl<C: Clock, const N: usize> FaultSink for FaultLog<C, N> {
+    fn fault(&mut self, fault: FaultStatus) {
+        let flags = fault.0 & EVENT_FLAGS;
+        if flags == self.last {
+            return;
+        }
+        self.last = flags;
+        self.open = flags != 0;
+        if self.open {
+            let status = DeviceStatus {
+                fault,
+                diagnostics: self.diagnostics,
+            };
+            self.record(status, self.velocity, RecoveryAction::None);
+        }
+    }
+    fn diagnostics(&mut self, diagnostics: DiagnosticStatus) {
+        self.diagnostics = diagnostics;
+        if let (true, Some(record)) = (self.open, self.records.back_mut()) {
+            record.status.diagnostics.0 |= diagnostics.0;
+        }
+    }
+    fn velocity(&mut self, velocity: i16) {
+        self.velocity = velocity;
+    }
+    fn cleared(&mut self) {
+        if let (true, Some(record)) = (self.open, self.records.back_mut()) {
+            if record.action == RecoveryAction::None {
+                record.action = RecoveryAction::ClearFault;
+            }
+        }
+        self.last = 0;
+        self.open = false;
+        self.diagnostics = DiagnosticStatus(0);
+    }
+}
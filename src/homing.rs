@@ -0,0 +1,195 @@
+//! Sensorless homing against a mechanical end stop.
+//!
+//! [Homing::home] drives the motor slowly toward the end stop with a reduced ITRIP level, so
+//! the DRV8873 limits the force on the mechanics, and waits for the [StallDetector] to report the
+//! hard stop. The motor then backs off for a while to take the load off the end stop. The
+//! [DRV8873Config] that was active before homing is written back afterwards, also when homing
+//! fails.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::current::{CurrentSense, IpropiScale};
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::registers::{DisITrip, ITripLvl};
+use crate::stall::{StallAction, StallConfig, StallDetector, StallEvent};
+use crate::{Drv8873Error, DRV8873};
+
+/// Speed, current and timing of a homing run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HomingConfig {
+    /// Signed Q15 speed toward the end stop.
+    pub speed: i16,
+    /// ITRIP level while driving toward the end stop.
+    pub itrip: ITripLvl,
+    /// Stall detection, the action is ignored since the motor always brakes at the end stop.
+    pub stall: StallConfig,
+    /// How long to drive away from the end stop in microseconds, at the homing speed.
+    pub back_off_us: u32,
+    /// Control loop period in microseconds.
+    pub tick_us: u32,
+    /// Homing fails with [Drv8873Error::Timeout] when no end stop is found within this many
+    /// microseconds.
+    pub timeout_us: u32,
+}
+
+impl Default for HomingConfig {
+    fn default() -> Self {
+        Self {
+            speed: -(i16::MAX / 4),
+            itrip: ITripLvl::Ampere4,
+            stall: StallConfig::default(),
+            back_off_us: 50_000,
+            tick_us: 1_000,
+            timeout_us: 10_000_000,
+        }
+    }
+}
+
+/// The result of a successful homing run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HomingReport {
+    /// Time spent driving toward the end stop in microseconds.
+    pub travel_us: u32,
+    /// The detected stall.
+    pub stall: StallEvent,
+}
+
+/// Sensorless homing routine.
+#[derive(Debug, Clone)]
+pub struct Homing {
+    config: HomingConfig,
+}
+
+impl Homing {
+    pub fn new(config: HomingConfig) -> Self {
+        Self { config }
+    }
+    pub fn config(&self) -> &HomingConfig {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: HomingConfig) {
+        self.config = config;
+    }
+    /// Home on the ITRIP regulation flags of the device.
+    pub async fn home<D, P, PW, MP, L>(
+        &self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+    ) -> Result<HomingReport, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        self.home_inner(drv, motor, delay, || None).await
+    }
+    /// Home on the ITRIP regulation flags and the IPROPI current, the current threshold is taken
+    /// from [StallConfig::current_ma].
+    pub async fn home_with_current<D, P, PW, MP, L>(
+        &self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+        adc: &mut impl CurrentSense,
+        scale: IpropiScale,
+    ) -> Result<HomingReport, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        self.home_inner(drv, motor, delay, || {
+            Some(scale.milliamps(adc.read_millivolts()))
+        })
+        .await
+    }
+    async fn home_inner<D, P, PW, MP, L>(
+        &self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+        current: impl FnMut() -> Option<u32>,
+    ) -> Result<HomingReport, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        let saved = drv.read_config().await?;
+        let result = self.run(drv, motor, delay, current).await;
+        let stopped = motor.brake();
+        let restored = drv.write_config(&saved).await;
+        let report = result?;
+        stopped?;
+        restored?;
+        Ok(report)
+    }
+    async fn run<D, P, PW, MP, L>(
+        &self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+        mut current: impl FnMut() -> Option<u32>,
+    ) -> Result<HomingReport, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        let config = &self.config;
+        drv.modify_cr4(|mut cr4| {
+            cr4.set_i_trip(DisITrip::Enabled);
+            cr4.set_i_trip_lvl(config.itrip);
+            cr4
+        })
+        .await?;
+        let mut detector = StallDetector::new(StallConfig {
+            action: StallAction::Brake,
+            ..config.stall
+        });
+
+        motor.drive_velocity(config.speed, drv, delay).await?;
+        let mut elapsed: u32 = 0;
+        let stall = loop {
+            delay.delay_us(config.tick_us).await;
+            elapsed = elapsed.saturating_add(config.tick_us);
+            // With itrip_rep set, ITRIP regulation also sets the FAULT bit. That's what the end
+            // stop looks like, only abort on the faults that shut the outputs down.
+            let fault = drv.read_fault().await?;
+            if fault.shutdown() {
+                return Err(Drv8873Error::Drv8873Fault(fault));
+            }
+            let event = detector
+                .check(drv, motor, current(), None, config.tick_us)
+                .await?;
+            if let Some(event) = event {
+                break event;
+            }
+            if elapsed >= config.timeout_us {
+                return Err(Drv8873Error::Timeout());
+            }
+        };
+
+        motor
+            .drive_velocity(config.speed.saturating_neg(), drv, delay)
+            .await?;
+        delay.delay_us(config.back_off_us).await;
+        motor.brake()?;
+        Ok(HomingReport {
+            travel_us: elapsed,
+            stall,
+        })
+    }
+}
@@ -0,0 +1,147 @@
+//! Power-up initialisation of a DRV8873.
+//!
+//! After power-up the device latches an undervoltage fault and all control registers are at
+//! their reset values. [DRV8873::init] wakes the device through nSLEEP, waits for the wake time,
+//! clears the latched faults, writes and verifies a [DRV8873Config] and optionally locks the
+//! registers. Every step reports its own [InitError] variant.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::registers::{DiagnosticStatus, FaultStatus, Lock};
+use crate::{DRV8873Config, Drv8873Error, DRV8873};
+
+/// Options of [DRV8873::init].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitOptions {
+    /// Time from nSLEEP going high until the device accepts SPI frames in microseconds.
+    pub wake_us: u32,
+    /// Lock the control registers after the configuration is verified.
+    pub lock: bool,
+}
+
+impl Default for InitOptions {
+    /// Wait the maximum wake time of 1ms and leave the registers unlocked.
+    fn default() -> Self {
+        Self {
+            wake_us: 1_000,
+            lock: false,
+        }
+    }
+}
+
+/// What [DRV8873::init] found and left behind.
+#[derive(Debug, Clone)]
+pub struct InitReport {
+    /// Whether the device was woken through the nSLEEP pin, without a pin it's assumed awake.
+    pub woke: bool,
+    /// The [FaultStatus] before clearing, a UVLO fault is expected after power-up.
+    pub initial_fault: FaultStatus,
+    pub initial_diagnostics: DiagnosticStatus,
+    /// The control registers before the configuration was written.
+    pub initial_config: DRV8873Config,
+    /// The configuration read back from the device after the last step.
+    pub config: DRV8873Config,
+}
+
+/// The step of [DRV8873::init] that failed.
+#[derive(Debug, Clone)]
+pub enum InitError {
+    /// Driving the nSLEEP pin failed.
+    Wake(Drv8873Error),
+    /// Reading the initial status or configuration failed.
+    ReadStatus(Drv8873Error),
+    /// Clearing the latched faults failed.
+    ClearFault(Drv8873Error),
+    /// A fault is still active after clearing, the condition persists.
+    FaultActive(FaultStatus),
+    /// Writing the configuration failed.
+    WriteConfig(Drv8873Error),
+    /// The configuration read back differs from the one written.
+    Verify {
+        expected: DRV8873Config,
+        found: DRV8873Config,
+    },
+    /// Locking the registers failed or they didn't read back as locked.
+    Lock(Drv8873Error),
+}
+
+impl<D: SpiDevice, P: StatefulOutputPin, L: FaultSink> DRV8873<D, P, L> {
+    /// Run the power-up sequence and configure the device.
+    ///
+    /// The lock field of `config` is ignored, the registers are unlocked to write them and only
+    /// locked again when [InitOptions::lock] is set.
+    pub async fn init(
+        &mut self,
+        config: &DRV8873Config,
+        options: InitOptions,
+        delay: &mut impl DelayNs,
+    ) -> Result<InitReport, InitError> {
+        let woke = self.pins.n_sleep.is_some();
+        if woke {
+            self.set_sleep(false).map_err(InitError::Wake)?;
+        }
+        delay.delay_us(options.wake_us).await;
+
+        let initial_fault = self.read_fault().await.map_err(InitError::ReadStatus)?;
+        let initial_diagnostics = self
+            .read_diagnostics()
+            .await
+            .map_err(InitError::ReadStatus)?;
+        let initial_config = self.read_config().await.map_err(InitError::ReadStatus)?;
+
+        if initial_fault.fault() || initial_diagnostics.0 != 0 {
+            self.clear_fault().await.map_err(InitError::ClearFault)?;
+            let fault = self.read_fault().await.map_err(InitError::ClearFault)?;
+            if fault.fault() {
+                return Err(InitError::FaultActive(fault));
+            }
+        }
+
+        let mut expected = config.clone();
+        expected.cr3.set_lock(Lock::Unlocked);
+        expected.cr3.set_clr_flt(false);
+        expected.cr4.set_en_olp(false);
+        // A locked device ignores writes to all but the lock field of ControlRegister3.
+        self.modify_cr3(|mut cr3| {
+            cr3.set_lock(Lock::Unlocked);
+            cr3
+        })
+        .await
+        .map_err(InitError::WriteConfig)?;
+        self.write_config(&expected)
+            .await
+            .map_err(InitError::WriteConfig)?;
+        let found = self.read_config().await.map_err(InitError::WriteConfig)?;
+        if found != expected {
+            return Err(InitError::Verify { expected, found });
+        }
+
+        let config = if options.lock {
+            self.modify_cr3(|mut cr3| {
+                cr3.set_lock(Lock::Locked);
+                cr3
+            })
+            .await
+            .map_err(InitError::Lock)?;
+            let cr3 = self.read_cr3().await.map_err(InitError::Lock)?;
+            if cr3.lock() != Lock::Locked {
+                return Err(InitError::Lock(Drv8873Error::InputError(
+                    "Registers not locked",
+                )));
+            }
+            DRV8873Config { cr3, ..found }
+        } else {
+            found
+        };
+
+        Ok(InitReport {
+            woke,
+            initial_fault,
+            initial_diagnostics,
+            initial_config,
+            config,
+        })
+    }
+}
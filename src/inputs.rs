@@ -1,3 +1,36 @@
+//! Driving the EN/IN1 and PH/IN2 inputs of a DRV8873 in the different [Mode]s.
+//!
+//! The [Decay] during the PWM off-time depends on the mode. In [Mode::PWM] both inputs are PWM
+//! channels and IN1 = IN2 = 0 leaves the outputs Hi-Z, so slow and fast decay are both
+//! available. In [Mode::PhaseEnable] the datasheet truth table has no Hi-Z input state, EN = 0
+//! always turns both low-side FETs on, so only slow decay is possible from the inputs. Fast decay
+//! in that mode would need PWM on the DISABLE pin, which [DRV8873] drives as a plain output pin.
+//! The same holds for [Mode::IndependentHalfBridge], where an input at 0 turns its low-side FET
+//! on.
+//!
+//! [Mode]: crate::registers::Mode
+//! [Mode::PWM]: crate::registers::Mode::PWM
+//! [Mode::PhaseEnable]: crate::registers::Mode::PhaseEnable
+//! [Mode::IndependentHalfBridge]: crate::registers::Mode::IndependentHalfBridge
+//! [DRV8873]: crate::DRV8873
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::Drv8873Error;
+
+/// How the current decays during the off-time of the PWM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decay {
+    /// The current recirculates through both low-side FETs (brake). The motor keeps most of its
+    /// torque at low duty cycles and the speed is close to proportional to the duty cycle.
+    Slow,
+    /// The outputs are Hi-Z (coast) and the current decays quickly through the body diodes. Only
+    /// available in [Mode::PWM], see the [module documentation](self) for why.
+    ///
+    /// [Mode::PWM]: crate::registers::Mode::PWM
+    Fast,
+}
+
 #[derive(core::fmt::Debug)]
 pub enum InputMode<PW: SetDutyCycle, P: StatefulOutputPin> {
     PhaseEnable(Option<(PW, P)>),
@@ -7,14 +40,139 @@ pub enum InputMode<PW: SetDutyCycle, P: StatefulOutputPin> {
 }
 
 impl<PW: SetDutyCycle, P: StatefulOutputPin> InputMode<PW, P> {
-    pub fn brake(&mut self, speed: u8) -> Result<(), Drv8873Error> {
+    /// Brake the motor by turning on both low-side FETs.
+    pub fn brake(&mut self) -> Result<(), Drv8873Error> {
+        match self {
+            InputMode::PhaseEnable(Some((en, _))) => {
+                en.set_duty_cycle_fully_off()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+            }
+            InputMode::PWM(Some((in1, in2))) => {
+                in1.set_duty_cycle_fully_on()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+                in2.set_duty_cycle_fully_on()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+            }
+            InputMode::IndependentHalfBridge(Some((in1, in2))) => {
+                in1.set_duty_cycle_fully_off()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+                in2.set_duty_cycle_fully_off()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+            }
+            InputMode::PhaseEnable(None)
+            | InputMode::PWM(None)
+            | InputMode::IndependentHalfBridge(None) => {
+                return Err(Drv8873Error::InputError(
+                    "No pins assigned for motor control",
+                ));
+            }
+            InputMode::InputDisabled => {
+                return Err(Drv8873Error::InputError("Inputs are disabled"));
+            }
+        }
+        Ok(())
+    }
+    /// Turn all inputs off. Only in [Mode::PWM] this leaves the outputs Hi-Z, in the other modes
+    /// the low-side FETs stay on. Use the DISABLE pin or the output disable bits in
+    /// [ControlRegister3] to coast in those modes.
+    ///
+    /// [Mode::PWM]: crate::registers::Mode::PWM
+    /// [ControlRegister3]: crate::registers::ControlRegister3
+    pub fn coast(&mut self) -> Result<(), Drv8873Error> {
+        match self {
+            InputMode::PhaseEnable(Some((en, ph))) => {
+                en.set_duty_cycle_fully_off()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+                ph.set_low()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+            }
+            InputMode::PWM(Some((in1, in2)))
+            | InputMode::IndependentHalfBridge(Some((in1, in2))) => {
+                in1.set_duty_cycle_fully_off()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+                in2.set_duty_cycle_fully_off()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+            }
+            InputMode::PhaseEnable(None)
+            | InputMode::PWM(None)
+            | InputMode::IndependentHalfBridge(None) => {
+                return Err(Drv8873Error::InputError(
+                    "No pins assigned for motor control",
+                ));
+            }
+            InputMode::InputDisabled => {
+                return Err(Drv8873Error::InputError("Inputs are disabled"));
+            }
+        }
+        Ok(())
+    }
+    /// Drive forward or backward with an on-time of `num / denom`, using `decay` during the
+    /// off-time.
+    ///
+    /// | Mode                  | Slow decay                   | Fast decay         |
+    /// |-----------------------|------------------------------|--------------------|
+    /// | Phase/enable          | EN = PWM, PH = direction     | not available      |
+    /// | PWM, forward          | IN1 = 1, IN2 = inverted PWM  | IN1 = PWM, IN2 = 0 |
+    /// | PWM, backward         | IN1 = inverted PWM, IN2 = 1  | IN1 = 0, IN2 = PWM |
+    /// | Independent, forward  | IN1 = PWM, IN2 = 0           | not available      |
+    /// | Independent, backward | IN1 = 0, IN2 = PWM           | not available      |
+    pub fn drive(
+        &mut self,
+        forward: bool,
+        num: u16,
+        denom: u16,
+        decay: Decay,
+    ) -> Result<(), Drv8873Error> {
+        if denom == 0 {
+            return Err(Drv8873Error::InputError("Duty cycle denominator is zero"));
+        }
+        let num = num.min(denom);
+        match (self, decay) {
+            (InputMode::PhaseEnable(Some((en, ph))), Decay::Slow) => {
+                ph.set_state(forward.into())
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+                en.set_duty_cycle_fraction(num, denom)
+                    .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+            }
+            (InputMode::PWM(Some((in1, in2))), Decay::Slow) => {
+                let (on, pwm) = if forward { (in1, in2) } else { (in2, in1) };
+                on.set_duty_cycle_fully_on()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set IN1/IN2"))?;
+                pwm.set_duty_cycle_fraction(denom - num, denom)
+                    .map_err(|_| Drv8873Error::InputError("Unable to set IN1/IN2"))?;
+            }
+            (InputMode::PWM(Some((in1, in2))), Decay::Fast)
+            | (InputMode::IndependentHalfBridge(Some((in1, in2))), Decay::Slow) => {
+                let (pwm, off) = if forward { (in1, in2) } else { (in2, in1) };
+                off.set_duty_cycle_fully_off()
+                    .map_err(|_| Drv8873Error::InputError("Unable to set IN1/IN2"))?;
+                pwm.set_duty_cycle_fraction(num, denom)
+                    .map_err(|_| Drv8873Error::InputError("Unable to set IN1/IN2"))?;
+            }
+            (InputMode::PhaseEnable(Some(_)), Decay::Fast)
+            | (InputMode::IndependentHalfBridge(Some(_)), Decay::Fast) => {
+                return Err(Drv8873Error::InputError(
+                    "Fast decay is only available in PWM mode",
+                ));
+            }
+            (InputMode::PhaseEnable(None), _)
+            | (InputMode::PWM(None), _)
+            | (InputMode::IndependentHalfBridge(None), _) => {
+                return Err(Drv8873Error::InputError(
+                    "No pins assigned for motor control",
+                ));
+            }
+            (InputMode::InputDisabled, _) => {
+                return Err(Drv8873Error::InputError("Inputs are disabled"));
+            }
+        }
         Ok(())
     }
     pub fn forward_with_speed(&mut self, speed: u8) -> Result<(), Drv8873Error> {
-        match &mut self.input_mode {
+        match self {
             InputMode::PhaseEnable(Some((en, ph))) => {
                 ph.set_high()
-                    .map_err(|e| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
                 en.set_duty_cycle_percent(speed)
                     .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
             }
@@ -23,27 +181,29 @@ impl<PW: SetDutyCycle, P: StatefulOutputPin> InputMode<PW, P> {
                     "No pins assigned for motor control",
                 ));
             }
-            InputMode::PWM(Some((in1, in2))) => {
+            InputMode::PWM(Some((in1, in2)))
+            | InputMode::IndependentHalfBridge(Some((in1, in2))) => {
                 in2.set_duty_cycle_fully_off()
-                    .map_err(|e| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
                 in1.set_duty_cycle_percent(speed)
                     .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
             }
-            InputMode::PWM(None) => {
+            InputMode::PWM(None) | InputMode::IndependentHalfBridge(None) => {
                 return Err(Drv8873Error::InputError(
                     "No pins assigned for motor control",
                 ));
             }
-            InputMode::IndependentHalfBridge(_) => todo!(),
-            InputMode::InputDisabled => todo!(),
+            InputMode::InputDisabled => {
+                return Err(Drv8873Error::InputError("Inputs are disabled"));
+            }
         }
         Ok(())
     }
     pub fn backward_with_speed(&mut self, speed: u8) -> Result<(), Drv8873Error> {
-        match &mut self.input_mode {
+        match self {
             InputMode::PhaseEnable(Some((en, ph))) => {
                 ph.set_low()
-                    .map_err(|e| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
                 en.set_duty_cycle_percent(speed)
                     .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
             }
@@ -52,19 +212,21 @@ impl<PW: SetDutyCycle, P: StatefulOutputPin> InputMode<PW, P> {
                     "No pins assigned for motor control",
                 ));
             }
-            InputMode::PWM(Some((in1, in2))) => {
+            InputMode::PWM(Some((in1, in2)))
+            | InputMode::IndependentHalfBridge(Some((in1, in2))) => {
                 in1.set_duty_cycle_fully_off()
                     .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
                 in2.set_duty_cycle_percent(speed)
-                    .map_err(|e| Drv8873Error::InputError("Unable to set PH_IN2"))?;
+                    .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))?;
             }
-            InputMode::PWM(None) => {
+            InputMode::PWM(None) | InputMode::IndependentHalfBridge(None) => {
                 return Err(Drv8873Error::InputError(
                     "No pins assigned for motor control",
                 ));
             }
-            InputMode::IndependentHalfBridge(_) => todo!(),
-            InputMode::InputDisabled => todo!(),
+            InputMode::InputDisabled => {
+                return Err(Drv8873Error::InputError("Inputs are disabled"));
+            }
         }
         Ok(())
     }
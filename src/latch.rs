@@ -0,0 +1,132 @@
+//! Pulse driver for bistable latching solenoids and door locks.
+//!
+//! A latching actuator only draws current while it changes state: a forward pulse latches it, a
+//! reverse pulse unlatches it and in between the bridge is left in Hi-Z. [LatchingActuator] drives
+//! the bridge in [Mode::PWM], where both inputs low put the outputs in Hi-Z.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::registers::{DiagnosticStatus, DisITrip, ITripLvl, Mode};
+use crate::{Drv8873Error, DRV8873};
+
+/// Settings shared by all pulses of a [LatchingActuator].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatchConfig {
+    /// Current limit during a pulse, leaves ITRIP untouched when [None].
+    pub itrip: Option<ITripLvl>,
+    /// Time the bridge is kept in Hi-Z after every pulse before another one can start, in
+    /// microseconds.
+    pub min_rest_us: u32,
+}
+
+impl Default for LatchConfig {
+    fn default() -> Self {
+        Self {
+            itrip: None,
+            min_rest_us: 100_000,
+        }
+    }
+}
+
+/// A bistable actuator connected between OUT1 and OUT2.
+pub struct LatchingActuator<PW: SetDutyCycle> {
+    in1: PW,
+    in2: PW,
+    config: LatchConfig,
+}
+
+impl<PW: SetDutyCycle> LatchingActuator<PW> {
+    pub fn new(in1: PW, in2: PW, config: LatchConfig) -> Self {
+        Self { in1, in2, config }
+    }
+    /// Release the PWM channels.
+    pub fn release(self) -> (PW, PW) {
+        (self.in1, self.in2)
+    }
+    /// Put the device in [Mode::PWM] and apply the ITRIP level from the [LatchConfig], leaves
+    /// the bridge in Hi-Z.
+    pub async fn configure<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+    ) -> Result<(), Drv8873Error> {
+        self.hi_z()?;
+        drv.modify_cr1(|mut cr1| {
+            cr1.set_mode(Mode::PWM);
+            cr1.set_spi_in(false);
+            cr1
+        })
+        .await?;
+        if let Some(level) = self.config.itrip {
+            drv.modify_cr4(|mut cr4| {
+                cr4.set_i_trip(DisITrip::Enabled);
+                cr4.set_i_trip_lvl(level);
+                cr4
+            })
+            .await?;
+        }
+        Ok(())
+    }
+    fn drive(&mut self, in1: bool, in2: bool) -> Result<(), Drv8873Error> {
+        let set = |pwm: &mut PW, on: bool| {
+            if on {
+                pwm.set_duty_cycle_fully_on()
+            } else {
+                pwm.set_duty_cycle_fully_off()
+            }
+        };
+        set(&mut self.in1, in1).map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+        set(&mut self.in2, in2).map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))
+    }
+    /// Put both outputs in Hi-Z.
+    pub fn hi_z(&mut self) -> Result<(), Drv8873Error> {
+        self.drive(false, false)
+    }
+    async fn pulse<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        delay: &mut impl DelayNs,
+        forward: bool,
+        duration_us: u32,
+    ) -> Result<DiagnosticStatus, Drv8873Error> {
+        self.drive(forward, !forward)?;
+        delay.delay_us(duration_us).await;
+        self.hi_z()?;
+        // Always rest, even after a fault, so an error can't lead to back to back pulses.
+        let status = match drv.read_fault().await {
+            Ok(fault) if fault.shutdown() => Err(Drv8873Error::Drv8873Fault(fault)),
+            Ok(_) => drv.read_diagnostics().await,
+            Err(e) => Err(e),
+        };
+        delay.delay_us(self.config.min_rest_us).await;
+        status
+    }
+    /// Drive OUT1 high and OUT2 low for `duration_us` microseconds, then return to Hi-Z and rest.
+    ///
+    /// Returns the [DiagnosticStatus] after the pulse, so the ITRIP flags can be used to see if
+    /// the actuator reached its end of travel, or an error when a fault shut the outputs down,
+    /// see [FaultStatus::shutdown].
+    ///
+    /// [FaultStatus::shutdown]: crate::registers::FaultStatus::shutdown
+    pub async fn pulse_forward<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        delay: &mut impl DelayNs,
+        duration_us: u32,
+    ) -> Result<DiagnosticStatus, Drv8873Error> {
+        self.pulse(drv, delay, true, duration_us).await
+    }
+    /// Drive OUT1 low and OUT2 high for `duration_us` microseconds, then return to Hi-Z and rest.
+    ///
+    /// See [LatchingActuator::pulse_forward].
+    pub async fn pulse_reverse<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        delay: &mut impl DelayNs,
+        duration_us: u32,
+    ) -> Result<DiagnosticStatus, Drv8873Error> {
+        self.pulse(drv, delay, false, duration_us).await
+    }
+}
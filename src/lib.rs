@@ -3,56 +3,220 @@
 //! An `async` library for interacting with the Texas Instruments [DRV8873SPW](https://www.ti.com/product/DRV8873) 40-V, 10-A H-Bridge
 //! motor driver.
 //!
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+#[cfg(feature = "embassy")]
+pub mod actor;
+pub mod array;
+#[cfg(any(test, feature = "std"))]
+pub mod capture;
+pub mod clock;
 pub mod config;
+pub mod current;
+pub mod encoder;
+pub mod faultlog;
+pub mod homing;
+pub mod init;
+pub mod inputs;
+pub mod latch;
+pub mod motor;
+pub mod outputs;
+pub mod parallel;
+pub mod pid;
+pub mod position;
+pub mod ramp;
 pub mod registers;
+pub mod selftest;
+#[cfg(any(test, feature = "std"))]
+pub mod sim;
+pub mod solenoid;
+pub mod speed;
+pub mod stall;
+pub mod stepper;
 mod tests;
+pub mod thermal;
+pub mod watchdog;
 
 #[doc(inline)]
 pub use crate::{
+    array::DRV8873Array,
     config::DRV8873Config,
+    inputs::{Decay, InputMode},
+    motor::Motor,
+    parallel::ParallelOutput,
     registers::{
         ControlRegister1, ControlRegister2, ControlRegister3, ControlRegister4, DisITrip, ITripLvl,
         Lock, Mode, OcpMode, OcpTRetry, RiseTime, Toff,
     },
 };
 
+use crate::faultlog::FaultSink;
 use crate::registers::*;
 use embedded_hal::digital::StatefulOutputPin;
-use embedded_hal_async::delay::DelayNs;
-use embedded_hal_async::digital::Wait;
 use embedded_hal_async::spi::SpiDevice;
 
-#[derive(core::fmt::Debug)]
+#[derive(core::fmt::Debug, Clone)]
 pub enum Drv8873Error {
     Drv8873Fault(FaultStatus),
     SpiError(),
     SleepError(),
     InputError(&'static str),
+    Timeout(),
+}
+
+/// What happens to the control pins when a [DRV8873] is released or dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SafeState {
+    /// Drive the DISABLE pin high, the outputs become Hi-Z.
+    pub disable: bool,
+    /// Drive the nSLEEP pin low, the device enters sleep mode and loses its configuration.
+    pub sleep: bool,
+}
+
+impl Default for SafeState {
+    /// Disable the outputs where the DISABLE pin is assigned. The device stays awake and keeps
+    /// its configuration.
+    fn default() -> Self {
+        Self {
+            disable: true,
+            sleep: false,
+        }
+    }
+}
+
+/// The SPI device, pins and fault log of a released [DRV8873].
+pub struct DRV8873Parts<D, P, L = ()> {
+    pub dev: D,
+    pub disable_pin: Option<P>,
+    pub n_sleep_pin: Option<P>,
+    pub fault_log: L,
+}
+
+/// The DISABLE and nSLEEP pins, they're put in the [SafeState] when dropped.
+struct ControlPins<P: StatefulOutputPin> {
+    disable: Option<P>,
+    n_sleep: Option<P>,
+    safe_state: SafeState,
+}
+
+impl<P: StatefulOutputPin> ControlPins<P> {
+    /// Errors are ignored, this also runs on drop.
+    fn apply_safe_state(&mut self) {
+        if let (true, Some(pin)) = (self.safe_state.disable, self.disable.as_mut()) {
+            let _ = pin.set_high();
+        }
+        if let (true, Some(pin)) = (self.safe_state.sleep, self.n_sleep.as_mut()) {
+            let _ = pin.set_low();
+        }
+    }
+}
+
+impl<P: StatefulOutputPin> Drop for ControlPins<P> {
+    fn drop(&mut self) {
+        self.apply_safe_state();
+    }
 }
 
 /// An instance of a DRV8873 device.
-pub struct DRV8873<D: SpiDevice, P: StatefulOutputPin> {
+///
+/// When dropped the DISABLE and nSLEEP pins are set according to the [SafeState]. The inputs
+/// are owned by a [Motor] or one of the other control handles. A [Motor], and through its coils
+/// a [Stepper], turns them off when dropped. [ParallelOutput], [Solenoid] and
+/// [LatchingActuator] leave their PWM channels as they are, turn them off first or rely on the
+/// DISABLE pin.
+///
+/// [Stepper]: crate::stepper::Stepper
+/// [Solenoid]: crate::solenoid::Solenoid
+/// [LatchingActuator]: crate::latch::LatchingActuator
+pub struct DRV8873<D: SpiDevice, P: StatefulOutputPin, L: FaultSink = ()> {
     dev: D,
-    fault_pin: Option<P>,
-    disable_pin: Option<P>,
-    n_sleep_pin: Option<P>,
+    pins: ControlPins<P>,
+    fault_log: L,
 }
 
 impl<D: SpiDevice, P: StatefulOutputPin> DRV8873<D, P> {
     pub fn new(dev: D) -> Self {
         Self {
             dev,
-            fault_pin: None,
-            disable_pin: None,
-            n_sleep_pin: None,
+            pins: ControlPins {
+                disable: None,
+                n_sleep: None,
+                safe_state: SafeState::default(),
+            },
+            fault_log: (),
         }
     }
+}
+
+impl<D: SpiDevice, P: StatefulOutputPin, L: FaultSink> DRV8873<D, P, L> {
+    /// Attach a fault log, every [FaultStatus] and [DiagnosticStatus] read through this driver
+    /// is passed to it. See [FaultLog].
+    ///
+    /// [FaultLog]: crate::faultlog::FaultLog
+    pub fn with_fault_log<L2: FaultSink>(self, fault_log: L2) -> DRV8873<D, P, L2> {
+        let DRV8873 { dev, pins, .. } = self;
+        DRV8873 {
+            dev,
+            pins,
+            fault_log,
+        }
+    }
+    pub fn fault_log(&self) -> &L {
+        &self.fault_log
+    }
+    pub fn fault_log_mut(&mut self) -> &mut L {
+        &mut self.fault_log
+    }
     /// Assign a disable pin, when set high this pin disables the output drivers of the DRV8873.
     pub fn with_disable_pin(mut self, disable_pin: P) -> Self {
-        self.disable_pin = Some(disable_pin);
+        self.pins.disable = Some(disable_pin);
+        self
+    }
+    /// Assign the nSLEEP pin, when set low the DRV8873 enters sleep mode.
+    pub fn with_n_sleep_pin(mut self, n_sleep_pin: P) -> Self {
+        self.pins.n_sleep = Some(n_sleep_pin);
+        self
+    }
+    /// Set the [SafeState] applied by [DRV8873::release] and on drop.
+    pub fn with_safe_state(mut self, safe_state: SafeState) -> Self {
+        self.pins.safe_state = safe_state;
         self
     }
+    /// Drive the DISABLE pin, while it's high the outputs are Hi-Z.
+    pub fn set_disabled(&mut self, disabled: bool) -> Result<(), Drv8873Error> {
+        let pin = self
+            .pins
+            .disable
+            .as_mut()
+            .ok_or(Drv8873Error::InputError("No disable pin assigned"))?;
+        pin.set_state(disabled.into())
+            .map_err(|_| Drv8873Error::InputError("Unable to set DISABLE"))
+    }
+    /// Drive the nSLEEP pin, the device sleeps while it's low.
+    pub fn set_sleep(&mut self, sleep: bool) -> Result<(), Drv8873Error> {
+        let pin = self
+            .pins
+            .n_sleep
+            .as_mut()
+            .ok_or(Drv8873Error::SleepError())?;
+        pin.set_state((!sleep).into())
+            .map_err(|_| Drv8873Error::SleepError())
+    }
+    /// Apply the [SafeState] and return the SPI device, the pins and the fault log so they can
+    /// be reused.
+    pub fn release(self) -> DRV8873Parts<D, P, L> {
+        let DRV8873 {
+            dev,
+            mut pins,
+            fault_log,
+        } = self;
+        pins.apply_safe_state();
+        DRV8873Parts {
+            dev,
+            disable_pin: pins.disable.take(),
+            n_sleep_pin: pins.n_sleep.take(),
+            fault_log,
+        }
+    }
     /// Read all the control registers from the device as a [DRV8873Config].
     pub async fn read_config(&mut self) -> Result<DRV8873Config, Drv8873Error> {
         DRV8873Config::read_config(&mut self.dev).await
@@ -63,12 +227,11 @@ impl<D: SpiDevice, P: StatefulOutputPin> DRV8873<D, P> {
         Ok(())
     }
     /// Reads all the control registers from the device as a [DRV8873Config] and allows them to be
-    /// modified through an [FnMut]
+    /// modified through an [FnOnce]
     pub async fn modify_config(
         &mut self,
-        f: fn(DRV8873Config) -> DRV8873Config,
-    ) -> Result<DRV8873Config, Drv8873Error>
-where {
+        f: impl FnOnce(DRV8873Config) -> DRV8873Config,
+    ) -> Result<DRV8873Config, Drv8873Error> {
         let cfg = f(self.read_config().await?);
         cfg.write_config(&mut self.dev).await?;
 
@@ -76,11 +239,15 @@ where {
     }
     /// Read the [FaultStatus] register from the device
     pub async fn read_fault(&mut self) -> Result<FaultStatus, Drv8873Error> {
-        FaultStatus::read(&mut self.dev).await
+        let fault = FaultStatus::read(&mut self.dev).await?;
+        self.fault_log.fault(fault);
+        Ok(fault)
     }
     /// Read the [DiagnosticStatus] register from the device
     pub async fn read_diagnostics(&mut self) -> Result<DiagnosticStatus, Drv8873Error> {
-        DiagnosticStatus::read(&mut self.dev).await
+        let diagnostics = DiagnosticStatus::read(&mut self.dev).await?;
+        self.fault_log.diagnostics(diagnostics);
+        Ok(diagnostics)
     }
     /// Reads [ControlRegister1] from the device, returns an error if the status byte in the SPI
     /// response contains a fault.
@@ -90,7 +257,7 @@ where {
     /// Reads [ControlRegister1] from the device and allows modification through a closure.
     pub async fn modify_cr1(
         &mut self,
-        f: fn(ControlRegister1) -> ControlRegister1,
+        f: impl FnOnce(ControlRegister1) -> ControlRegister1,
     ) -> Result<ControlRegister1, Drv8873Error> {
         let cr = f(self.read_cr1().await?);
         cr.write(&mut self.dev).await?;
@@ -105,7 +272,7 @@ where {
     /// Reads [ControlRegister2] from the device and allows modification through a closure.
     pub async fn modify_cr2(
         &mut self,
-        f: fn(ControlRegister2) -> ControlRegister2,
+        f: impl FnOnce(ControlRegister2) -> ControlRegister2,
     ) -> Result<ControlRegister2, Drv8873Error> {
         let cr = f(self.read_cr2().await?);
         cr.write(&mut self.dev).await?;
@@ -120,7 +287,7 @@ where {
     /// Reads [ControlRegister3] from the device and allows modification through a closure.
     pub async fn modify_cr3(
         &mut self,
-        f: fn(ControlRegister3) -> ControlRegister3,
+        f: impl FnOnce(ControlRegister3) -> ControlRegister3,
     ) -> Result<ControlRegister3, Drv8873Error> {
         let cr = f(self.read_cr3().await?);
         cr.write(&mut self.dev).await?;
@@ -135,7 +302,7 @@ where {
     /// Reads [ControlRegister4] from the device and allows modification through a closure.
     pub async fn modify_cr4(
         &mut self,
-        f: fn(ControlRegister4) -> ControlRegister4,
+        f: impl FnOnce(ControlRegister4) -> ControlRegister4,
     ) -> Result<ControlRegister4, Drv8873Error> {
         let cr = f(self.read_cr4().await?);
         cr.write(&mut self.dev).await?;
@@ -149,6 +316,7 @@ where {
             cr3
         })
         .await?;
+        self.fault_log.cleared();
         Ok(())
     }
 }
@@ -0,0 +1,312 @@
+//! Motor control on top of the [InputMode] of a DRV8873.
+//!
+//! A [Motor] keeps track of the commanded speed and direction of a brushed DC motor connected
+//! between OUT1 and OUT2 and provides acceleration limited ramping through a [Ramp]. Speeds are
+//! either whole percents or signed Q15 velocities, which use the full resolution of the PWM
+//! timer.
+//!
+//! Reversing a loaded motor at speed causes large current spikes, so every direction change goes
+//! through a [ReversalPolicy]. [Motor::drive], [Motor::drive_velocity] and [Motor::ramp_step]
+//! apply it, the synchronous [Motor::set_speed] and [Motor::set_velocity] can't wait and reject
+//! a reversal unless the policy is [ReversalPolicy::Immediate].
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::inputs::{Decay, InputMode};
+use crate::ramp::{Profile, Ramp, RampConfig, FULL_SCALE};
+use crate::{Drv8873Error, DRV8873};
+
+/// How a [Motor] changes the direction while it is running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReversalPolicy {
+    /// Switch directly to the new speed.
+    Immediate,
+    /// Brake for `duration_us` microseconds before driving in the new direction.
+    Brake { duration_us: u32 },
+    /// Let the outputs float for `duration_us` microseconds before driving in the new direction.
+    /// In [Mode::PWM] the inputs are turned off and in [Mode::IndependentHalfBridge] the output
+    /// disable bits in [ControlRegister3] are used. Those bits have no effect in
+    /// [Mode::PhaseEnable], there the DISABLE pin is driven high instead and reversing fails with
+    /// [Drv8873Error::InputError] when the driver has no DISABLE pin.
+    ///
+    /// [Mode::PWM]: crate::registers::Mode::PWM
+    /// [Mode::IndependentHalfBridge]: crate::registers::Mode::IndependentHalfBridge
+    /// [Mode::PhaseEnable]: crate::registers::Mode::PhaseEnable
+    /// [ControlRegister3]: crate::registers::ControlRegister3
+    Coast { duration_us: u32 },
+    /// Ramp the speed down to zero with a `deceleration` in full scale units per second, updating
+    /// it every `tick_us` microseconds. A zero `deceleration` stops the motor at once.
+    Ramp { deceleration: u32, tick_us: u32 },
+}
+
+impl Default for ReversalPolicy {
+    /// Brake for 10ms.
+    fn default() -> Self {
+        ReversalPolicy::Brake {
+            duration_us: 10_000,
+        }
+    }
+}
+
+/// A brushed DC motor driven by the H-bridge of a DRV8873.
+pub struct Motor<PW: SetDutyCycle, P: StatefulOutputPin> {
+    input: InputMode<PW, P>,
+    velocity: i16,
+    reversal: ReversalPolicy,
+    decay: Decay,
+}
+
+impl<PW: SetDutyCycle, P: StatefulOutputPin> Motor<PW, P> {
+    /// Create a motor, the [Decay] defaults to fast decay in [Mode::PWM] and slow decay in the
+    /// other modes.
+    ///
+    /// [Mode::PWM]: crate::registers::Mode::PWM
+    pub fn new(input: InputMode<PW, P>) -> Self {
+        let decay = match input {
+            InputMode::PWM(_) => Decay::Fast,
+            _ => Decay::Slow,
+        };
+        Self {
+            input,
+            velocity: 0,
+            reversal: ReversalPolicy::default(),
+            decay,
+        }
+    }
+    /// Set the [ReversalPolicy] applied when the direction changes.
+    pub fn with_reversal(mut self, reversal: ReversalPolicy) -> Self {
+        self.reversal = reversal;
+        self
+    }
+    pub fn reversal(&self) -> ReversalPolicy {
+        self.reversal
+    }
+    pub fn set_reversal(&mut self, reversal: ReversalPolicy) {
+        self.reversal = reversal;
+    }
+    /// Set the [Decay] mode used during the PWM off-time.
+    pub fn with_decay(mut self, decay: Decay) -> Self {
+        self.decay = decay;
+        self
+    }
+    pub fn decay(&self) -> Decay {
+        self.decay
+    }
+    /// Set the [Decay] mode, it takes effect with the next speed update.
+    pub fn set_decay(&mut self, decay: Decay) {
+        self.decay = decay;
+    }
+    /// Release the [InputMode], the inputs are left as they are. Dropping the motor instead
+    /// turns them off, see [Motor::coast]. Only in [Mode::PWM] that leaves the outputs Hi-Z, in
+    /// the other modes the motor brakes through the low-side FETs until the DISABLE pin or the
+    /// [SafeState] of the [DRV8873] disables the outputs.
+    ///
+    /// [Mode::PWM]: crate::registers::Mode::PWM
+    /// [SafeState]: crate::SafeState
+    pub fn into_inner(mut self) -> InputMode<PW, P> {
+        core::mem::replace(&mut self.input, InputMode::InputDisabled)
+    }
+    /// The last commanded speed rounded to the nearest percent, negative when running backward.
+    pub fn speed(&self) -> i8 {
+        let speed = self.velocity as i32 * 100;
+        let rounding = speed.signum() * FULL_SCALE / 2;
+        ((speed + rounding) / FULL_SCALE) as i8
+    }
+    /// The last commanded speed as a signed Q15 fraction of full duty.
+    pub fn velocity(&self) -> i16 {
+        self.velocity
+    }
+    /// Set the speed in percent, positive values drive forward and negative values backward.
+    ///
+    /// Reversing a running motor is rejected with [Drv8873Error::InputError] unless the
+    /// [ReversalPolicy] is [ReversalPolicy::Immediate], use [Motor::drive] to apply the policy.
+    pub fn set_speed(&mut self, speed: i8) -> Result<(), Drv8873Error> {
+        self.check_direction(speed as i32)?;
+        self.apply_speed(speed)
+    }
+    /// Set the speed in percent like [Motor::set_speed], applying the [ReversalPolicy] first when
+    /// the direction changes. The new velocity is passed to the fault log of `drv`.
+    pub async fn drive<D: SpiDevice, P2: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        speed: i8,
+        drv: &mut DRV8873<D, P2, L>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Drv8873Error> {
+        if (self.velocity as i32 * speed as i32) < 0 {
+            self.reverse(drv, delay).await?;
+        }
+        self.apply_speed(speed)?;
+        drv.fault_log_mut().velocity(self.velocity);
+        Ok(())
+    }
+    /// Set a signed Q15 velocity like [Motor::set_velocity], applying the [ReversalPolicy] first
+    /// when the direction changes. The new velocity is passed to the fault log of `drv`.
+    pub async fn drive_velocity<D: SpiDevice, P2: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        velocity: i16,
+        drv: &mut DRV8873<D, P2, L>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Drv8873Error> {
+        if (self.velocity as i32 * velocity as i32) < 0 {
+            self.reverse(drv, delay).await?;
+        }
+        self.apply_velocity(velocity)?;
+        drv.fault_log_mut().velocity(self.velocity);
+        Ok(())
+    }
+    fn check_direction(&self, speed: i32) -> Result<(), Drv8873Error> {
+        if self.reversal != ReversalPolicy::Immediate && (self.velocity as i32 * speed) < 0 {
+            return Err(Drv8873Error::InputError(
+                "Reversing needs the reversal policy, use Motor::drive",
+            ));
+        }
+        Ok(())
+    }
+    fn apply_speed(&mut self, speed: i8) -> Result<(), Drv8873Error> {
+        let speed = speed.clamp(-100, 100);
+        self.input
+            .drive(speed >= 0, speed.unsigned_abs() as u16, 100, self.decay)?;
+        self.velocity = (speed as i32 * FULL_SCALE / 100) as i16;
+        Ok(())
+    }
+    /// Set a signed Q15 velocity without looking at the [ReversalPolicy].
+    pub(crate) fn apply_velocity(&mut self, velocity: i16) -> Result<(), Drv8873Error> {
+        let velocity = velocity.max(-i16::MAX);
+        self.input.drive(
+            velocity >= 0,
+            velocity.unsigned_abs(),
+            FULL_SCALE as u16,
+            self.decay,
+        )?;
+        self.velocity = velocity;
+        Ok(())
+    }
+    /// Bring the motor to a stop according to the [ReversalPolicy].
+    async fn reverse<D: SpiDevice, P2: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P2, L>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Drv8873Error> {
+        match self.reversal {
+            ReversalPolicy::Immediate => {}
+            ReversalPolicy::Brake { duration_us } => {
+                self.brake()?;
+                delay.delay_us(duration_us).await;
+            }
+            ReversalPolicy::Coast { duration_us } => {
+                self.coast()?;
+                match self.input {
+                    InputMode::IndependentHalfBridge(_) => {
+                        Self::set_outputs_disabled(drv, true).await?;
+                        delay.delay_us(duration_us).await;
+                        Self::set_outputs_disabled(drv, false).await?;
+                    }
+                    InputMode::PhaseEnable(_) => {
+                        drv.set_disabled(true)?;
+                        delay.delay_us(duration_us).await;
+                        drv.set_disabled(false)?;
+                    }
+                    _ => delay.delay_us(duration_us).await,
+                }
+            }
+            ReversalPolicy::Ramp {
+                deceleration,
+                tick_us,
+            } => {
+                let mut ramp = Ramp::new(RampConfig {
+                    acceleration: deceleration,
+                    deceleration,
+                    profile: Profile::Linear,
+                });
+                ramp.set_current(self.velocity);
+                ramp.set_target(0);
+                while !ramp.is_done() {
+                    self.apply_velocity(ramp.step(tick_us))?;
+                    delay.delay_us(tick_us).await;
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn set_outputs_disabled<D: SpiDevice, P2: StatefulOutputPin, L: FaultSink>(
+        drv: &mut DRV8873<D, P2, L>,
+        disabled: bool,
+    ) -> Result<(), Drv8873Error> {
+        drv.modify_cr3(|mut cr3| {
+            cr3.set_out1_dis(disabled);
+            cr3.set_out2_dis(disabled);
+            cr3
+        })
+        .await?;
+        Ok(())
+    }
+    /// Brake the motor by turning on both low-side FETs.
+    pub fn brake(&mut self) -> Result<(), Drv8873Error> {
+        self.input.brake()?;
+        self.velocity = 0;
+        Ok(())
+    }
+    /// Turn all inputs off, see [InputMode::coast].
+    pub fn coast(&mut self) -> Result<(), Drv8873Error> {
+        self.input.coast()?;
+        self.velocity = 0;
+        Ok(())
+    }
+    /// Set a signed Q15 velocity, the sign selects the direction and the magnitude is the
+    /// fraction of full duty. `i16::MIN` is treated as full duty backward.
+    ///
+    /// Reversing a running motor is rejected like in [Motor::set_speed], use
+    /// [Motor::drive_velocity] to apply the [ReversalPolicy].
+    pub fn set_velocity(&mut self, velocity: i16) -> Result<(), Drv8873Error> {
+        self.check_direction(velocity as i32)?;
+        self.apply_velocity(velocity)
+    }
+    /// Advance `ramp` by `dt_us` microseconds and apply the resulting speed.
+    ///
+    /// A ramp to the other direction slows down to zero first, the [ReversalPolicy] is applied
+    /// at that point before the ramp continues.
+    pub async fn ramp_step<D: SpiDevice, P2: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        ramp: &mut Ramp,
+        drv: &mut DRV8873<D, P2, L>,
+        delay: &mut impl DelayNs,
+        dt_us: u32,
+    ) -> Result<(), Drv8873Error> {
+        let velocity = ramp.step(dt_us);
+        if velocity == 0 && (self.velocity as i32 * ramp.target() as i32) < 0 {
+            self.reverse(drv, delay).await?;
+        }
+        self.apply_velocity(velocity)?;
+        drv.fault_log_mut().velocity(self.velocity);
+        Ok(())
+    }
+    /// Run `ramp` until its target is reached, updating the speed every `tick_us` microseconds.
+    ///
+    /// Dropping the returned future cancels the ramp and leaves the motor at the last applied
+    /// speed, which is also the current speed of `ramp`. It can then be retargeted and run again.
+    pub async fn run_ramp<D: SpiDevice, P2: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        ramp: &mut Ramp,
+        drv: &mut DRV8873<D, P2, L>,
+        delay: &mut impl DelayNs,
+        tick_us: u32,
+    ) -> Result<(), Drv8873Error> {
+        while !ramp.is_done() {
+            self.ramp_step(ramp, drv, delay, tick_us).await?;
+            delay.delay_us(tick_us).await;
+        }
+        Ok(())
+    }
+}
+
+impl<PW: SetDutyCycle, P: StatefulOutputPin> Drop for Motor<PW, P> {
+    /// Turn the inputs off, errors are ignored. This brakes the motor outside of [Mode::PWM].
+    ///
+    /// [Mode::PWM]: crate::registers::Mode::PWM
+    fn drop(&mut self) {
+        let _ = self.input.coast();
+    }
+}
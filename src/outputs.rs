@@ -0,0 +1,109 @@
+//! Output state truth tables of the DRV8873.
+//!
+//! [BridgeInputs] collects everything that determines the state of OUT1 and OUT2 and
+//! [BridgeInputs::outputs] evaluates it according to the control tables in the datasheet. It does
+//! not talk to the device, which makes it usable for runtime sanity checks, simulation and
+//! exhaustive testing of control code.
+use crate::registers::Mode;
+use crate::DRV8873Config;
+
+/// One of the two half-bridge outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HalfBridge {
+    Out1,
+    Out2,
+}
+
+/// The state of a single half-bridge output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputState {
+    /// High-side FET on.
+    High,
+    /// Low-side FET on.
+    Low,
+    /// Both FETs off.
+    HiZ,
+}
+
+/// The signals that determine the output states of the H-bridge.
+#[derive(Debug)]
+pub struct BridgeInputs {
+    /// Input mode from [ControlRegister1](crate::ControlRegister1).
+    pub mode: Mode,
+    /// When set the `en_in1` and `ph_in2` bits are used instead of the input pins.
+    pub spi_in: bool,
+    /// Level of the EN/IN1 pin.
+    pub in1: bool,
+    /// Level of the PH/IN2 pin.
+    pub in2: bool,
+    /// The `en_in1` bit of [ControlRegister3](crate::ControlRegister3).
+    pub en_in1: bool,
+    /// The `ph_in2` bit of [ControlRegister3](crate::ControlRegister3).
+    pub ph_in2: bool,
+    /// Puts OUT1 in Hi-Z, only in [Mode::IndependentHalfBridge].
+    pub out1_dis: bool,
+    /// Puts OUT2 in Hi-Z, only in [Mode::IndependentHalfBridge].
+    pub out2_dis: bool,
+    /// Level of the DISABLE pin.
+    pub disable: bool,
+    /// Level of the nSLEEP pin.
+    pub n_sleep: bool,
+}
+
+impl BridgeInputs {
+    /// Collect the register controlled signals from a [DRV8873Config] together with the pin
+    /// levels.
+    pub fn from_config(
+        cfg: &DRV8873Config,
+        in1: bool,
+        in2: bool,
+        disable: bool,
+        n_sleep: bool,
+    ) -> Self {
+        Self {
+            mode: cfg.cr1.mode(),
+            spi_in: cfg.cr1.spi_in(),
+            in1,
+            in2,
+            en_in1: cfg.cr3.en_in1(),
+            ph_in2: cfg.cr3.ph_in2(),
+            out1_dis: cfg.cr3.out1_dis(),
+            out2_dis: cfg.cr3.out2_dis(),
+            disable,
+            n_sleep,
+        }
+    }
+
+    /// Evaluate the truth table and return the states of (OUT1, OUT2).
+    pub fn outputs(&self) -> (OutputState, OutputState) {
+        use OutputState::*;
+
+        if !self.n_sleep || self.disable {
+            return (HiZ, HiZ);
+        }
+        let (in1, in2) = if self.spi_in {
+            (self.en_in1, self.ph_in2)
+        } else {
+            (self.in1, self.in2)
+        };
+        let half_bridge = |input: bool| if input { High } else { Low };
+        match self.mode {
+            Mode::PhaseEnable => match (in1, in2) {
+                (false, _) => (Low, Low),
+                (true, false) => (Low, High),
+                (true, true) => (High, Low),
+            },
+            Mode::PWM => match (in1, in2) {
+                (false, false) => (HiZ, HiZ),
+                (false, true) => (Low, High),
+                (true, false) => (High, Low),
+                (true, true) => (Low, Low),
+            },
+            Mode::IndependentHalfBridge => (
+                if self.out1_dis { HiZ } else { half_bridge(in1) },
+                if self.out2_dis { HiZ } else { half_bridge(in2) },
+            ),
+            Mode::InputDisabled => (HiZ, HiZ),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Paralleled half bridges driving a single high-current load.
+//!
+//! In [Mode::IndependentHalfBridge] OUT1 and OUT2 can be tied together to share the current of a
+//! unidirectional load connected between the outputs and ground. A [ParallelOutput] drives IN1
+//! and IN2 in lock-step, so both PWM channels should come from the same timer to keep the edges
+//! aligned.
+//!
+//! The load must be tied to ground: the duty cycle is the high-side on-time and [ParallelOutput::off]
+//! drives the outputs low. A load tied to VM would be fully on while the outputs are off.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::registers::{DiagnosticStatus, DisITrip, ITripLvl, Mode};
+use crate::{Drv8873Error, DRV8873};
+
+/// Both half bridges of a DRV8873 driven as a single output.
+pub struct ParallelOutput<PW: SetDutyCycle> {
+    in1: PW,
+    in2: PW,
+}
+
+impl<PW: SetDutyCycle> ParallelOutput<PW> {
+    pub fn new(in1: PW, in2: PW) -> Self {
+        Self { in1, in2 }
+    }
+    /// Release the PWM channels.
+    pub fn release(self) -> (PW, PW) {
+        (self.in1, self.in2)
+    }
+    /// Put the device in [Mode::IndependentHalfBridge] with both outputs enabled and the same
+    /// ITRIP level on both half bridges. The combined output regulates at twice `level`.
+    pub async fn configure<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        level: ITripLvl,
+    ) -> Result<(), Drv8873Error> {
+        drv.modify_cr1(|mut cr1| {
+            cr1.set_mode(Mode::IndependentHalfBridge);
+            cr1.set_spi_in(false);
+            cr1
+        })
+        .await?;
+        drv.modify_cr3(|mut cr3| {
+            cr3.set_out1_dis(false);
+            cr3.set_out2_dis(false);
+            cr3
+        })
+        .await?;
+        drv.modify_cr4(|mut cr4| {
+            cr4.set_i_trip(DisITrip::Enabled);
+            cr4.set_i_trip_lvl(level);
+            cr4
+        })
+        .await?;
+        Ok(())
+    }
+    /// Set the duty cycle of both half bridges, as a fraction `num / denom` of the time the load is
+    /// connected to VM. `num` is clamped to `denom`, a zero `denom` is rejected.
+    pub fn set_duty_cycle_fraction(&mut self, num: u16, denom: u16) -> Result<(), Drv8873Error> {
+        if denom == 0 {
+            return Err(Drv8873Error::InputError("Duty cycle denominator is zero"));
+        }
+        let num = num.min(denom);
+        self.in1
+            .set_duty_cycle_fraction(num, denom)
+            .map_err(|_| Drv8873Error::InputError("Unable to set EN_IN1"))?;
+        self.in2
+            .set_duty_cycle_fraction(num, denom)
+            .map_err(|_| Drv8873Error::InputError("Unable to set PH_IN2"))
+    }
+    /// Set the duty cycle of both half bridges in percent, values above 100 are clamped.
+    pub fn set_duty_cycle_percent(&mut self, percent: u8) -> Result<(), Drv8873Error> {
+        self.set_duty_cycle_fraction(percent as u16, 100)
+    }
+    /// Drive both outputs low, which turns the ground-tied load off.
+    pub fn off(&mut self) -> Result<(), Drv8873Error> {
+        self.set_duty_cycle_fraction(0, 1)
+    }
+    /// Read the [DiagnosticStatus] of the device, a fault on either half bridge is a fault of the
+    /// combined output and is returned as an error together with the [FaultStatus].
+    ///
+    /// [FaultStatus]: crate::registers::FaultStatus
+    pub async fn check_fault<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+    ) -> Result<DiagnosticStatus, Drv8873Error> {
+        let diag = drv.read_diagnostics().await?;
+        if diag.out1_fault() || diag.out2_fault() {
+            return Err(Drv8873Error::Drv8873Fault(drv.read_fault().await?));
+        }
+        Ok(diag)
+    }
+}
@@ -0,0 +1,78 @@
+//! A fixed-point PID controller.
+//!
+//! All arithmetic is done in integers so the controller is cheap on cores without an FPU. Gains
+//! are Q16.16 fixed-point numbers, `1 << 16` is a gain of one output unit per unit of error.
+
+/// A gain of one in Q16.16.
+pub const ONE: i32 = 1 << 16;
+
+/// Proportional, integral and derivative gains in Q16.16. The integral gain is per second of
+/// accumulated error and the derivative gain is per unit of error change per second.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PidGains {
+    pub kp: i32,
+    pub ki: i32,
+    pub kd: i32,
+}
+
+/// A PID controller with output clamping and integrator clamping as anti-windup.
+#[derive(Debug, Clone)]
+pub struct Pid {
+    gains: PidGains,
+    output_min: i32,
+    output_max: i32,
+    /// Accumulated error in error units times microseconds.
+    integral: i64,
+    prev_error: Option<i32>,
+}
+
+impl Pid {
+    pub fn new(gains: PidGains, output_min: i32, output_max: i32) -> Self {
+        Self {
+            gains,
+            output_min,
+            output_max,
+            integral: 0,
+            prev_error: None,
+        }
+    }
+    pub fn gains(&self) -> &PidGains {
+        &self.gains
+    }
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.gains = gains;
+    }
+    /// Clear the integral and derivative state.
+    pub fn reset(&mut self) {
+        self.integral = 0;
+        self.prev_error = None;
+    }
+    fn output(&self, error: i32, derivative: i64) -> i32 {
+        let p = self.gains.kp as i64 * error as i64;
+        let i = self.gains.ki as i64 * self.integral / 1_000_000;
+        let d = self.gains.kd as i64 * derivative;
+        ((p + i + d) >> 16).clamp(self.output_min as i64, self.output_max as i64) as i32
+    }
+    /// Run the controller for `dt_us` microseconds of `error` and return the new output.
+    ///
+    /// When `freeze_integral` is set the integral is held, for example while the output is
+    /// limited by something outside of the controller. The integral term itself is always kept
+    /// within the output limits so it can't wind up while the output saturates.
+    pub fn update(&mut self, error: i32, dt_us: u32, freeze_integral: bool) -> i32 {
+        let derivative = match (self.prev_error, dt_us) {
+            (Some(prev), dt) if dt > 0 => (error as i64 - prev as i64) * 1_000_000 / dt as i64,
+            _ => 0,
+        };
+        self.prev_error = Some(error);
+
+        if !freeze_integral {
+            self.integral += error as i64 * dt_us as i64;
+            if self.gains.ki != 0 {
+                let bound = |limit: i32| ((limit as i64) << 16) * 1_000_000 / self.gains.ki as i64;
+                let (a, b) = (bound(self.output_min), bound(self.output_max));
+                self.integral = self.integral.clamp(a.min(b), a.max(b));
+            }
+        }
+        self.output(error, derivative)
+    }
+}
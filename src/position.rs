@@ -0,0 +1,172 @@
+//! Closed-loop position control for linear actuators.
+//!
+//! A [PositionController] moves a motor with an [Encoder] to a target count. The position error
+//! is turned into a speed command by a proportional gain, limited to a maximum speed and passed
+//! through a [Ramp] to limit the acceleration. Once the position is within the tolerance the
+//! bridge brakes to hold it.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::encoder::Encoder;
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::pid::ONE;
+use crate::ramp::{Profile, Ramp, RampConfig, FULL_SCALE};
+use crate::{Drv8873Error, DRV8873};
+
+/// Limits and tuning of a [PositionController].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionConfig {
+    /// A move completes once the position is within this many counts of the target.
+    pub tolerance: u32,
+    /// Maximum speed as a Q15 fraction of full duty.
+    pub max_speed: i16,
+    /// Acceleration and deceleration in full scale units per second, zero doesn't limit them.
+    pub acceleration: u32,
+    /// Speed command per count of position error in Q16.16.
+    pub gain: i32,
+    /// Control loop period in microseconds.
+    pub tick_us: u32,
+    /// A move that doesn't complete within this many microseconds fails with
+    /// [Drv8873Error::Timeout].
+    pub timeout_us: u32,
+}
+
+impl Default for PositionConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 10,
+            max_speed: i16::MAX,
+            acceleration: 4 * FULL_SCALE as u32,
+            gain: 64 * ONE,
+            tick_us: 1_000,
+            timeout_us: 10_000_000,
+        }
+    }
+}
+
+/// Position controller for a motor with an encoder.
+pub struct PositionController<E: Encoder> {
+    encoder: E,
+    config: PositionConfig,
+    ramp: Ramp,
+}
+
+impl<E: Encoder> PositionController<E> {
+    pub fn new(encoder: E, config: PositionConfig) -> Self {
+        Self {
+            encoder,
+            ramp: Ramp::new(Self::ramp_config(&config)),
+            config,
+        }
+    }
+    fn ramp_config(config: &PositionConfig) -> RampConfig {
+        RampConfig {
+            acceleration: config.acceleration,
+            deceleration: config.acceleration,
+            profile: Profile::Linear,
+        }
+    }
+    /// Release the encoder.
+    pub fn into_inner(self) -> E {
+        self.encoder
+    }
+    pub fn config(&self) -> &PositionConfig {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: PositionConfig) {
+        self.ramp.set_config(Self::ramp_config(&config));
+        self.config = config;
+    }
+    /// The current position in counts.
+    pub fn position(&mut self) -> i32 {
+        self.encoder.sample().count
+    }
+    /// Speed command for a position error, before acceleration limiting.
+    fn speed_command(&self, error: i32) -> i16 {
+        let max = self.config.max_speed.max(0) as i64;
+        ((self.config.gain as i64 * error as i64) >> 16).clamp(-max, max) as i16
+    }
+    /// Brake the motor to hold the current position.
+    pub fn hold<PW: SetDutyCycle, MP: StatefulOutputPin>(
+        &mut self,
+        motor: &mut Motor<PW, MP>,
+    ) -> Result<(), Drv8873Error> {
+        self.ramp.set_target(0);
+        self.ramp.set_current(0);
+        motor.brake()
+    }
+    /// Abort a move, the motor brakes immediately.
+    pub fn abort<PW: SetDutyCycle, MP: StatefulOutputPin>(
+        &mut self,
+        motor: &mut Motor<PW, MP>,
+    ) -> Result<(), Drv8873Error> {
+        self.hold(motor)
+    }
+    /// Move to `target` counts and hold the position.
+    ///
+    /// Returns the final position once it's within the tolerance, [Drv8873Error::Timeout] when
+    /// the move takes too long or [Drv8873Error::Drv8873Fault] when a fault shuts the outputs
+    /// down, see [FaultStatus::shutdown].
+    /// The motor brakes in all cases. Dropping the future stops updating the motor, call
+    /// [PositionController::abort] to brake it.
+    ///
+    /// [FaultStatus::shutdown]: crate::registers::FaultStatus::shutdown
+    pub async fn move_to<D, P, PW, MP, L>(
+        &mut self,
+        target: i32,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+    ) -> Result<i32, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        let result = self.run(target, drv, motor, delay).await;
+        self.hold(motor)?;
+        result
+    }
+    async fn run<D, P, PW, MP, L>(
+        &mut self,
+        target: i32,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+    ) -> Result<i32, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        let mut elapsed: u32 = 0;
+        loop {
+            let position = self.encoder.sample().count;
+            let error = target.wrapping_sub(position);
+            if error.unsigned_abs() <= self.config.tolerance {
+                return Ok(position);
+            }
+            // ITRIP regulation sets the FAULT bit with itrip_rep, that isn't a reason to abort.
+            let fault = drv.read_fault().await?;
+            if fault.shutdown() {
+                return Err(Drv8873Error::Drv8873Fault(fault));
+            }
+            if elapsed >= self.config.timeout_us {
+                return Err(Drv8873Error::Timeout());
+            }
+            self.ramp.set_target(self.speed_command(error));
+            motor
+                .ramp_step(&mut self.ramp, drv, delay, self.config.tick_us)
+                .await?;
+            delay.delay_us(self.config.tick_us).await;
+            elapsed = elapsed.saturating_add(self.config.tick_us);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Acceleration limited speed ramps.
+//!
+//! A [Ramp] moves a signed speed towards a target speed with a limited acceleration, either with
+//! a constant acceleration ([Profile::Linear]) or with a limited jerk ([Profile::SCurve]). Speeds
+//! are signed Q15 fractions of full duty, `i16::MAX` is full speed forward and `-i16::MAX` full
+//! speed backward.
+//!
+//! When the target has the opposite sign of the current speed the ramp first decelerates to zero
+//! and then accelerates in the new direction. The generator is advanced explicitly with
+//! [Ramp::step], so it can be retargeted or cancelled between any two steps.
+
+/// Full scale speed of a ramp.
+pub const FULL_SCALE: i32 = i16::MAX as i32;
+
+const MICRO: i64 = 1_000_000;
+
+/// The shape of the speed profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    /// Constant acceleration, the acceleration changes instantly.
+    Linear,
+    /// The acceleration changes with at most `jerk`, in full scale units per second². A zero
+    /// `jerk` doesn't limit it, which is the same as [Profile::Linear].
+    SCurve { jerk: u32 },
+}
+
+/// Limits of a [Ramp], a zero limit doesn't limit and the speed jumps to the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RampConfig {
+    /// Acceleration while the speed magnitude increases, in full scale units per second.
+    pub acceleration: u32,
+    /// Deceleration while the speed magnitude decreases, in full scale units per second.
+    pub deceleration: u32,
+    pub profile: Profile,
+}
+
+impl Default for RampConfig {
+    /// Accelerate and decelerate from standstill to full speed in half a second.
+    fn default() -> Self {
+        Self {
+            acceleration: 2 * FULL_SCALE as u32,
+            deceleration: 2 * FULL_SCALE as u32,
+            profile: Profile::Linear,
+        }
+    }
+}
+
+/// Integer square root, rounded down.
+pub(crate) fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/// A speed ramp generator.
+#[derive(Debug, Clone)]
+pub struct Ramp {
+    config: RampConfig,
+    /// Current speed in millionths of a unit.
+    speed: i64,
+    /// Current rate of change in millionths of a unit per second.
+    rate: i64,
+    target: i16,
+}
+
+impl Ramp {
+    /// Create a ramp at standstill.
+    pub fn new(config: RampConfig) -> Self {
+        Self {
+            config,
+            speed: 0,
+            rate: 0,
+            target: 0,
+        }
+    }
+    pub fn config(&self) -> &RampConfig {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: RampConfig) {
+        self.config = config;
+    }
+    /// The speed the ramp is currently at.
+    pub fn current(&self) -> i16 {
+        (self.speed / MICRO) as i16
+    }
+    /// Set the current speed, for example after the motor was stopped outside of the ramp.
+    pub fn set_current(&mut self, speed: i16) {
+        self.speed = speed as i64 * MICRO;
+        self.rate = 0;
+    }
+    pub fn target(&self) -> i16 {
+        self.target
+    }
+    /// Set a new target speed, this can be done at any time including in the middle of a ramp.
+    pub fn set_target(&mut self, target: i16) {
+        self.target = target.max(-i16::MAX);
+    }
+    /// Stop ramping and hold the current speed.
+    pub fn cancel(&mut self) {
+        self.set_target(self.current());
+        self.speed = self.target as i64 * MICRO;
+        self.rate = 0;
+    }
+    /// Returns `true` when the target speed is reached.
+    pub fn is_done(&self) -> bool {
+        self.speed == self.target as i64 * MICRO
+    }
+    /// Advance the ramp by `dt_us` microseconds and return the new speed.
+    pub fn step(&mut self, dt_us: u32) -> i16 {
+        let target = self.target as i64 * MICRO;
+        // Reversing goes through zero first.
+        let goal = if self.speed != 0 && (self.speed > 0) != (target > 0) && target != 0 {
+            0
+        } else {
+            target
+        };
+        let error = goal - self.speed;
+        if error == 0 {
+            self.rate = 0;
+            return self.current();
+        }
+        let speeding_up = goal.abs() > self.speed.abs();
+        let max_rate = if speeding_up {
+            self.config.acceleration
+        } else {
+            self.config.deceleration
+        } as i64
+            * MICRO;
+        if max_rate == 0 {
+            self.rate = 0;
+            self.speed = goal;
+            return self.current();
+        }
+        let dt = dt_us as i64;
+
+        let rate = match self.config.profile {
+            Profile::Linear | Profile::SCurve { jerk: 0 } => max_rate * error.signum(),
+            Profile::SCurve { jerk } => {
+                // Limit the rate so it can be brought back to zero at the goal with the given jerk.
+                let stop_rate =
+                    isqrt((2 * jerk as u64).saturating_mul(error.unsigned_abs())) as i64 * 1000;
+                let jerk = jerk as i64 * MICRO;
+                let desired = max_rate.min(stop_rate.max(1)) * error.signum();
+                let max_change = jerk.saturating_mul(dt) / MICRO;
+                let rate = if desired > self.rate {
+                    self.rate.saturating_add(max_change).min(desired)
+                } else {
+                    self.rate.saturating_sub(max_change).max(desired)
+                };
+                // Slow down immediately if the stopping distance requires it.
+                if rate.signum() == desired.signum() && rate.abs() > desired.abs() {
+                    desired
+                } else {
+                    rate
+                }
+            }
+        };
+        self.rate = rate;
+        let delta = rate.saturating_mul(dt) / MICRO;
+        self.speed = if (error > 0 && delta >= error) || (error < 0 && delta <= error) {
+            self.rate = if goal == target { 0 } else { self.rate };
+            goal
+        } else {
+            self.speed + delta
+        };
+        self.current()
+    }
+}
@@ -1,4 +1,5 @@
-use core::fmt::Debug;
+use core::fmt::{Debug, Display, Formatter};
+use core::str::FromStr;
 
 use crate::Drv8873Error;
 use bitfield::bitfield;
@@ -9,8 +10,10 @@ bitfield! {
     pub struct CommandByte(u8);
     impl Debug;
 
-    u8, address, set_address: 5, 1;
-    read_bit, set_read_bit: 6;
+    /// Register address the command applies to.
+    pub(crate) u8, address, set_address: 5, 1;
+    /// Set for a read command, cleared for a write command.
+    pub(crate) read_bit, set_read_bit: 6;
 }
 impl CommandByte {
     /// Construct a write command byte to the specified address.
@@ -29,6 +32,7 @@ impl CommandByte {
 }
 /// The status byte should have it's 2 most significant bits set, the other 6 correspond to
 /// faults as described in the [FaultStatus] register.
+#[cfg_attr(not(any(test, feature = "std")), allow(dead_code))]
 pub(crate) fn get_status(status_byte: u8) -> Option<FaultStatus> {
     if status_byte != 0b11000000 {
         Some(FaultStatus(status_byte & 0b00111111))
@@ -193,6 +197,7 @@ impl<D: SpiDevice> WriteableRegister<D> for ControlRegister2 {}
 impl<D: SpiDevice> WriteableRegister<D> for ControlRegister3 {}
 impl<D: SpiDevice> WriteableRegister<D> for ControlRegister4 {}
 bitfield! {
+    #[derive(Clone, Copy)]
     pub struct FaultStatus(u8);
     impl Debug;
 
@@ -215,6 +220,7 @@ bitfield! {
 }
 
 bitfield! {
+    #[derive(Clone, Copy)]
     pub struct DiagnosticStatus(u8);
     impl Debug;
 
@@ -236,6 +242,30 @@ bitfield! {
     pub ol1, _ : 7;
 }
 
+impl FaultStatus {
+    /// A fault that shuts the outputs down: overcurrent, overtemperature or undervoltage. With
+    /// `itrip_rep` or `otw_rep` set, ITRIP regulation and the overtemperature warning also set
+    /// [FaultStatus::fault] while the device keeps running.
+    pub fn shutdown(&self) -> bool {
+        self.ocp() || self.tsd() || self.uvlo() || self.cpuv()
+    }
+}
+
+impl DiagnosticStatus {
+    /// Overcurrent or open load detected on half bridge 1.
+    pub fn out1_fault(&self) -> bool {
+        self.ocp_h1() || self.ocp_l1() || self.ol1()
+    }
+    /// Overcurrent or open load detected on half bridge 2.
+    pub fn out2_fault(&self) -> bool {
+        self.ocp_h2() || self.ocp_l2() || self.ol2()
+    }
+    /// Either output is limited by ITRIP current regulation.
+    pub fn in_current_regulation(&self) -> bool {
+        self.itrip1() || self.itrip2()
+    }
+}
+
 bitfield! {
     pub struct ControlRegister1(u8);
     impl Debug;
@@ -313,7 +343,7 @@ bitfield! {
 }
 /// Determines the Toff time set in [ControlRegister1]
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum Toff {
     Us20 = 0b00,
     #[default]
@@ -324,7 +354,7 @@ pub enum Toff {
 
 /// Determines the rise time set in [ControlRegister1]
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum RiseTime {
     VoltPerUs53_2 = 0b000,
     VoltPerUs34_0 = 0b001,
@@ -337,9 +367,25 @@ pub enum RiseTime {
     VoltPerUs2_6 = 0b111,
 }
 
+impl RiseTime {
+    /// The output slew rate in millivolts per microsecond.
+    pub fn millivolts_per_us(&self) -> u32 {
+        match self {
+            RiseTime::VoltPerUs53_2 => 53_200,
+            RiseTime::VoltPerUs34_0 => 34_000,
+            RiseTime::VoltPerUs18_3 => 18_300,
+            RiseTime::VoltPerUs13_0 => 13_000,
+            RiseTime::VoltPerUs10_8 => 10_800,
+            RiseTime::VoltPerUs7_9 => 7_900,
+            RiseTime::VoltPerUs5_3 => 5_300,
+            RiseTime::VoltPerUs2_6 => 2_600,
+        }
+    }
+}
+
 /// Determines the device mode set in [ControlRegister1]
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum Mode {
     PhaseEnable = 0b00,
     #[default]
@@ -349,7 +395,7 @@ pub enum Mode {
 }
 /// Determines the overcurrent protection mode set in [ControlRegister2]
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum OcpMode {
     #[default]
     LatchedFault = 0b00,
@@ -359,7 +405,7 @@ pub enum OcpMode {
 }
 /// Determines the overcurrent protection retry time set in [ControlRegister2]
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum OcpTRetry {
     Ms0_5 = 0b00,
     Ms1 = 0b01,
@@ -369,7 +415,7 @@ pub enum OcpTRetry {
 }
 /// Locks the control registers except for these bits and the clr_flt bit.
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum Lock {
     #[default]
     Unlocked = 0b100,
@@ -377,7 +423,7 @@ pub enum Lock {
 }
 /// Disables the current regulation for OUT1, OUT2 or both.
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum DisITrip {
     #[default]
     Enabled = 0b00,
@@ -387,7 +433,7 @@ pub enum DisITrip {
 }
 /// Sets the current regulation amperage.
 #[repr(u8)]
-#[derive(Debug, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, FromPrimitive, IntoPrimitive, Default)]
 pub enum ITripLvl {
     Ampere4 = 0b00,
     Ampere5_4 = 0b01,
@@ -395,3 +441,105 @@ pub enum ITripLvl {
     Ampere6_5 = 0b10,
     Ampere7 = 0b11,
 }
+
+impl ITripLvl {
+    /// All levels from lowest to highest.
+    pub const LEVELS: [ITripLvl; 4] = [
+        ITripLvl::Ampere4,
+        ITripLvl::Ampere5_4,
+        ITripLvl::Ampere6_5,
+        ITripLvl::Ampere7,
+    ];
+    /// The regulation current in milliamps.
+    pub fn milliamps(&self) -> u32 {
+        match self {
+            ITripLvl::Ampere4 => 4_000,
+            ITripLvl::Ampere5_4 => 5_400,
+            ITripLvl::Ampere6_5 => 6_500,
+            ITripLvl::Ampere7 => 7_000,
+        }
+    }
+    /// The lowest level strictly above `milliamps`, if there is one.
+    pub fn above(milliamps: u32) -> Option<ITripLvl> {
+        Self::LEVELS
+            .into_iter()
+            .find(|level| level.milliamps() > milliamps)
+    }
+}
+
+/// Returned when a textual field value does not match any of the accepted spellings.
+#[derive(Debug, PartialEq)]
+pub struct ParseFieldError;
+
+/// Implements [Display] and [FromStr] for a register field enum, mapping every variant to the
+/// text used in the [DRV8873Config](crate::DRV8873Config) key=value format.
+macro_rules! field_text {
+    ($ty:ty { $($variant:ident => $text:literal),+ $(,)? }) => {
+        impl Display for $ty {
+            fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+                f.write_str(match self {
+                    $(Self::$variant => $text,)+
+                })
+            }
+        }
+        impl FromStr for $ty {
+            type Err = ParseFieldError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($text => Ok(Self::$variant),)+
+                    _ => Err(ParseFieldError),
+                }
+            }
+        }
+    };
+}
+field_text!(Toff {
+    Us20 => "20us",
+    Us40 => "40us",
+    Us60 => "60us",
+    Us80 => "80us",
+});
+field_text!(RiseTime {
+    VoltPerUs53_2 => "53.2V/us",
+    VoltPerUs34_0 => "34.0V/us",
+    VoltPerUs18_3 => "18.3V/us",
+    VoltPerUs13_0 => "13.0V/us",
+    VoltPerUs10_8 => "10.8V/us",
+    VoltPerUs7_9 => "7.9V/us",
+    VoltPerUs5_3 => "5.3V/us",
+    VoltPerUs2_6 => "2.6V/us",
+});
+field_text!(Mode {
+    PhaseEnable => "ph-en",
+    PWM => "pwm",
+    IndependentHalfBridge => "half-bridge",
+    InputDisabled => "disabled",
+});
+field_text!(OcpMode {
+    LatchedFault => "latched",
+    AutomaticRetry => "retry",
+    ReportOnly => "report",
+    NoAction => "none",
+});
+field_text!(OcpTRetry {
+    Ms0_5 => "0.5ms",
+    Ms1 => "1ms",
+    Ms2 => "2ms",
+    Ms4 => "4ms",
+});
+field_text!(Lock {
+    Unlocked => "unlocked",
+    Locked => "locked",
+});
+field_text!(DisITrip {
+    Enabled => "none",
+    Out1Disabled => "out1",
+    Out2Disabled => "out2",
+    Disabled => "both",
+});
+field_text!(ITripLvl {
+    Ampere4 => "4A",
+    Ampere5_4 => "5.4A",
+    Ampere6_5 => "6.5A",
+    Ampere7 => "7A",
+});
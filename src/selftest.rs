@@ -0,0 +1,253 @@
+//! Startup and end-of-line self-test.
+//!
+//! [DRV8873::self_test] checks the SPI link by writing test patterns to the control registers
+//! and reading them back, checks the framing of the status byte on every frame, runs the standby
+//! open-load test, checks that `clr_flt` clears the latched faults and compares the nFAULT pin
+//! with [FaultStatus::fault]. The configuration is restored afterwards.
+//!
+//! The open-load test only runs in standby, all inputs must be off while testing. `spi_in`,
+//! `en_in1` and `ph_in2` are cleared for the duration of the test and the patterns leave the
+//! mode, `spi_in`, `en_in1`, `ph_in2`, `ocp_mode` and `dis_cpuv` bits at their values, so the
+//! register patterns never drive the outputs or turn the protection off. Those bits are only
+//! read back with the value they hold.
+use embedded_hal::digital::{InputPin, StatefulOutputPin};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::registers::{
+    CommandByte, ControlRegister1, ControlRegister2, ControlRegister3, ControlRegister4,
+    DiagnosticStatus, FaultStatus, Lock, Register,
+};
+use crate::{Drv8873Error, DRV8873};
+
+/// Patterns written to every control register, masked to its writable bits.
+const PATTERNS: [u8; 4] = [0x55, 0xAA, 0xFF, 0x00];
+
+/// Options of [DRV8873::self_test].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestOptions {
+    /// How long the standby open-load test is given to complete in microseconds.
+    pub olp_wait_us: u32,
+}
+
+impl Default for SelfTestOptions {
+    fn default() -> Self {
+        Self { olp_wait_us: 5_000 }
+    }
+}
+
+/// A test pattern that didn't read back from a control register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterMismatch {
+    pub addr: u8,
+    pub written: u8,
+    pub read: u8,
+}
+
+/// The results of [DRV8873::self_test].
+#[derive(Debug, Clone, Copy)]
+pub struct SelfTestReport {
+    /// The first pattern that failed for each of [ControlRegister1] to [ControlRegister4].
+    pub registers: [Option<RegisterMismatch>; 4],
+    /// Every status byte had its two most significant bits set and matched the [FaultStatus].
+    pub framing: bool,
+    /// The standby open-load test completed within [SelfTestOptions::olp_wait_us].
+    pub open_load_done: bool,
+    /// The [DiagnosticStatus] after the open-load test.
+    pub open_load: DiagnosticStatus,
+    /// The [FaultStatus] before clearing the faults.
+    pub latched_fault: FaultStatus,
+    /// No fault was left after writing `clr_flt`.
+    pub fault_cleared: bool,
+    /// The nFAULT pin matched [FaultStatus::fault] before and after clearing.
+    pub nfault_agrees: bool,
+}
+
+impl SelfTestReport {
+    pub fn registers_ok(&self) -> bool {
+        self.registers.iter().all(Option::is_none)
+    }
+    /// The open-load test completed and found a load on both outputs.
+    pub fn open_load_ok(&self) -> bool {
+        self.open_load_done && !self.open_load.ol1() && !self.open_load.ol2()
+    }
+    pub fn passed(&self) -> bool {
+        self.registers_ok()
+            && self.framing
+            && self.open_load_ok()
+            && self.fault_cleared
+            && self.nfault_agrees
+    }
+}
+
+impl<D: SpiDevice, P: StatefulOutputPin, L: FaultSink> DRV8873<D, P, L> {
+    /// Run the self-test, `n_fault` is the input connected to the nFAULT pin.
+    ///
+    /// Fails only when the SPI device or the nFAULT input returns an error, the outcome of every
+    /// check is in the [SelfTestReport].
+    pub async fn self_test(
+        &mut self,
+        options: SelfTestOptions,
+        delay: &mut impl DelayNs,
+        n_fault: &mut impl InputPin,
+    ) -> Result<SelfTestReport, Drv8873Error> {
+        let saved = self.read_config().await?;
+        let mut framing = true;
+
+        // With the SPI inputs cleared and the outputs following the input pins the patterns can't
+        // drive the outputs. ControlRegister3 is written first, so en_in1 and ph_in2 are already
+        // cleared when spi_in is.
+        let mut base = saved.clone();
+        base.cr1.set_spi_in(false);
+        base.cr3.set_lock(Lock::Unlocked);
+        base.cr3.set_clr_flt(false);
+        base.cr3.set_en_in1(false);
+        base.cr3.set_ph_in2(false);
+        base.cr4.set_en_olp(false);
+        self.frame(
+            &mut framing,
+            CommandByte::write(ControlRegister3::ADDR),
+            base.cr3.0,
+        )
+        .await?;
+        self.write_config(&base).await?;
+
+        // The address, the value to restore, the bits the patterns are written to, the bits that
+        // are fixed and the bits that are compared.
+        let registers = [
+            // The mode and spi_in keep their values, another mode could turn on the low-side
+            // FETs and spi_in would hand the outputs to the patterns in ControlRegister3.
+            (
+                ControlRegister1::ADDR,
+                base.cr1.0,
+                0xDC,
+                base.cr1.0 & 0x23,
+                0xFF,
+            ),
+            // ocp_mode and dis_cpuv keep their values so the protection stays on.
+            (
+                ControlRegister2::ADDR,
+                base.cr2.0,
+                0xEC,
+                base.cr2.0 & 0x13,
+                0xFF,
+            ),
+            // The lock field is kept unlocked and clr_flt reads back as zero. en_in1 and ph_in2
+            // stay cleared, so the outputs aren't driven whatever spi_in is.
+            (
+                ControlRegister3::ADDR,
+                base.cr3.0,
+                0x0C,
+                base.cr3.0 & 0x70,
+                0x7F,
+            ),
+            // The reserved bit and en_olp, which would start an open-load test, aren't written.
+            (ControlRegister4::ADDR, base.cr4.0, 0x3F, 0x00, 0x7F),
+        ];
+        let mut mismatches = [None; 4];
+        for ((addr, restore, mask, fixed, checked), mismatch) in
+            registers.into_iter().zip(&mut mismatches)
+        {
+            for pattern in PATTERNS {
+                let written = pattern & mask | fixed;
+                self.frame(&mut framing, CommandByte::write(addr), written)
+                    .await?;
+                let read = self.frame(&mut framing, CommandByte::read(addr), 0).await?;
+                if read & checked != written && mismatch.is_none() {
+                    *mismatch = Some(RegisterMismatch {
+                        addr,
+                        written,
+                        read,
+                    });
+                }
+            }
+            self.frame(&mut framing, CommandByte::write(addr), restore)
+                .await?;
+        }
+
+        let mut olp = base.cr4;
+        olp.set_en_olp(true);
+        self.frame(
+            &mut framing,
+            CommandByte::write(ControlRegister4::ADDR),
+            olp.0,
+        )
+        .await?;
+        delay.delay_us(options.olp_wait_us).await;
+        let cr4 = self
+            .frame(&mut framing, CommandByte::read(ControlRegister4::ADDR), 0)
+            .await?;
+        let open_load_done = !ControlRegister4(cr4).en_olp();
+        let open_load = DiagnosticStatus(
+            self.frame(&mut framing, CommandByte::read(DiagnosticStatus::ADDR), 0)
+                .await?,
+        );
+        self.fault_log.diagnostics(open_load);
+
+        let latched_fault = self.checked_fault(&mut framing).await?;
+        let mut nfault_agrees = Self::n_fault_agrees(n_fault, latched_fault)?;
+        self.clear_fault().await?;
+        let fault = self.checked_fault(&mut framing).await?;
+        nfault_agrees &= Self::n_fault_agrees(n_fault, fault)?;
+        let fault_cleared = !fault.fault();
+
+        let mut restore = saved.clone();
+        restore.cr3.set_lock(Lock::Unlocked);
+        self.write_config(&restore).await?;
+        if saved.cr3.lock() == Lock::Locked {
+            self.modify_cr3(|mut cr3| {
+                cr3.set_lock(Lock::Locked);
+                cr3
+            })
+            .await?;
+        }
+
+        Ok(SelfTestReport {
+            registers: mismatches,
+            framing,
+            open_load_done,
+            open_load,
+            latched_fault,
+            fault_cleared,
+            nfault_agrees,
+        })
+    }
+    /// Transfer a single frame and return the data byte, clears `framing` when the status byte
+    /// is malformed.
+    async fn frame(
+        &mut self,
+        framing: &mut bool,
+        cb: CommandByte,
+        data: u8,
+    ) -> Result<u8, Drv8873Error> {
+        let mut buf = [cb.0, data];
+        self.dev
+            .transfer_in_place(&mut buf)
+            .await
+            .map_err(|_| Drv8873Error::SpiError())?;
+        *framing &= buf[0] & 0b1100_0000 == 0b1100_0000;
+        Ok(buf[1])
+    }
+    /// Read the [FaultStatus] and check that the status byte reports the same faults. Like
+    /// [DRV8873::read_fault] it's passed to the fault log.
+    async fn checked_fault(&mut self, framing: &mut bool) -> Result<FaultStatus, Drv8873Error> {
+        let mut buf = [CommandByte::read(FaultStatus::ADDR).0, 0];
+        self.dev
+            .transfer_in_place(&mut buf)
+            .await
+            .map_err(|_| Drv8873Error::SpiError())?;
+        *framing &= buf[0] & 0b1100_0000 == 0b1100_0000;
+        *framing &= buf[0] & 0b0011_1111 == buf[1] & 0b0011_1111;
+        let fault = FaultStatus(buf[1]);
+        self.fault_log.fault(fault);
+        Ok(fault)
+    }
+    /// nFAULT is pulled low while the fault bit is set.
+    fn n_fault_agrees(pin: &mut impl InputPin, fault: FaultStatus) -> Result<bool, Drv8873Error> {
+        let low = pin
+            .is_low()
+            .map_err(|_| Drv8873Error::InputError("Unable to read nFAULT"))?;
+        Ok(low == fault.fault())
+    }
+}
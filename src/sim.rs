@@ -0,0 +1,343 @@
+//! A behavioural model of the DRV8873 for host side testing.
+//!
+//! [SimDevice] implements both the blocking and the async [SpiDevice] traits and responds to
+//! frames the way the IC does: every 16 bit frame returns the status byte followed by the current
+//! contents of the addressed register. Register writes respect [Lock], `clr_flt` and `en_olp`
+//! clear themselves, and faults can be injected with [SimDevice::inject].
+//!
+//! Clones of a [SimDevice] share their state, so a test can keep a handle to inject faults and
+//! inspect registers while the driver owns the other one.
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+use core::convert::Infallible;
+use embedded_hal::spi::{ErrorType, Operation};
+
+use crate::outputs::HalfBridge;
+use crate::registers::{
+    CommandByte, ControlRegister1, ControlRegister2, ControlRegister3, ControlRegister4,
+    DiagnosticStatus, FaultStatus, Lock, Register,
+};
+use crate::DRV8873Config;
+
+/// One of the four FETs of the two half bridges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fet {
+    HighSide1,
+    LowSide1,
+    HighSide2,
+    LowSide2,
+}
+
+/// Fault conditions that can be injected into a [SimDevice]. All of them latch until cleared
+/// with `clr_flt` in [ControlRegister3].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimFault {
+    /// Overcurrent on the given FET.
+    Ocp(Fet),
+    /// Overtemperature shutdown.
+    Tsd,
+    /// Overtemperature warning.
+    Otw,
+    /// Supply undervoltage lockout.
+    Uvlo,
+    /// Charge-pump undervoltage.
+    Cpuv,
+    /// Open load detected on the given output.
+    OpenLoad(HalfBridge),
+}
+
+// Bit positions in the FaultStatus register.
+const OLD: u8 = 1 << 0;
+const TSD: u8 = 1 << 1;
+const OCP: u8 = 1 << 2;
+const CPUV: u8 = 1 << 3;
+const UVLO: u8 = 1 << 4;
+const OTW: u8 = 1 << 5;
+const FAULT: u8 = 1 << 6;
+
+// Bit positions in the DiagnosticStatus register.
+const OCP_L2: u8 = 1 << 0;
+const OCP_H2: u8 = 1 << 1;
+const OCP_L1: u8 = 1 << 2;
+const OCP_H1: u8 = 1 << 3;
+const ITRIP2: u8 = 1 << 4;
+const ITRIP1: u8 = 1 << 5;
+const OL2: u8 = 1 << 6;
+const OL1: u8 = 1 << 7;
+
+#[derive(Debug)]
+struct State {
+    fault: u8,
+    diag: u8,
+    cr1: u8,
+    cr2: u8,
+    cr3: u8,
+    cr4: u8,
+    open_load: (bool, bool),
+    /// Bytes clocked in so far for the frame in progress.
+    frame: Vec<u8>,
+    frames: usize,
+}
+
+impl State {
+    fn fault_register(&self) -> u8 {
+        let cr2 = ControlRegister2(self.cr2);
+        let mut active = self.fault & (OLD | TSD | OCP | CPUV | UVLO);
+        if cr2.otw_rep() {
+            active |= self.fault & OTW;
+        }
+        if cr2.itrip_rep() {
+            active |= self.diag & (ITRIP1 | ITRIP2);
+        }
+        if active != 0 {
+            self.fault | FAULT
+        } else {
+            self.fault
+        }
+    }
+
+    fn status_byte(&self) -> u8 {
+        0b1100_0000 | (self.fault_register() & 0b0011_1111)
+    }
+
+    fn read(&self, addr: u8) -> u8 {
+        match addr {
+            FaultStatus::ADDR => self.fault_register(),
+            DiagnosticStatus::ADDR => self.diag,
+            ControlRegister1::ADDR => self.cr1,
+            ControlRegister2::ADDR => self.cr2,
+            ControlRegister3::ADDR => self.cr3,
+            ControlRegister4::ADDR => self.cr4,
+            _ => 0,
+        }
+    }
+
+    fn locked(&self) -> bool {
+        ControlRegister3(self.cr3).lock() == Lock::Locked
+    }
+
+    fn write(&mut self, addr: u8, byte: u8) {
+        match addr {
+            ControlRegister1::ADDR if !self.locked() => self.cr1 = byte,
+            ControlRegister2::ADDR if !self.locked() => self.cr2 = byte,
+            ControlRegister3::ADDR => self.write_cr3(byte),
+            ControlRegister4::ADDR if !self.locked() => self.write_cr4(byte),
+            _ => {}
+        }
+    }
+
+    fn write_cr3(&mut self, byte: u8) {
+        let new = ControlRegister3(byte);
+        let mut cr3 = if self.locked() {
+            ControlRegister3(self.cr3)
+        } else {
+            ControlRegister3(byte)
+        };
+        // Only the two defined lock sequences have an effect on the lock field.
+        let lock = (byte >> 4) & 0b111;
+        if lock == Lock::Locked as u8 || lock == Lock::Unlocked as u8 {
+            cr3.set_lock(Lock::from(lock));
+        } else {
+            cr3.set_lock(ControlRegister3(self.cr3).lock());
+        }
+        if new.clr_flt() {
+            self.fault = 0;
+            self.diag = 0;
+        }
+        cr3.set_clr_flt(false);
+        self.cr3 = cr3.0;
+    }
+
+    fn write_cr4(&mut self, byte: u8) {
+        let mut cr4 = ControlRegister4(byte);
+        if cr4.en_olp() {
+            let (ol1, ol2) = self.open_load;
+            if ol1 {
+                self.diag |= OL1;
+            }
+            if ol2 {
+                self.diag |= OL2;
+            }
+            if ol1 || ol2 {
+                self.fault |= OLD;
+            }
+            cr4.set_en_olp(false);
+        }
+        self.cr4 = cr4.0;
+    }
+
+    /// Clock a single byte through the device and return the byte shifted out on SDO.
+    fn exchange(&mut self, mosi: u8) -> u8 {
+        self.frame.push(mosi);
+        if self.frame.len() == 1 {
+            return self.status_byte();
+        }
+        let cb = CommandByte(self.frame[0]);
+        let data = self.frame[1];
+        self.frame.clear();
+        self.frames += 1;
+        let current = self.read(cb.address());
+        if !cb.read_bit() {
+            self.write(cb.address(), data);
+        }
+        current
+    }
+}
+
+/// A simulated DRV8873 on an SPI bus.
+#[derive(Debug, Clone)]
+pub struct SimDevice {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for SimDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimDevice {
+    /// Create a device with all registers at their reset values and no faults.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                fault: 0,
+                diag: 0,
+                cr1: ControlRegister1::default().0,
+                cr2: ControlRegister2::default().0,
+                cr3: ControlRegister3::default().0,
+                cr4: ControlRegister4::default().0,
+                open_load: (false, false),
+                frame: Vec::new(),
+                frames: 0,
+            })),
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        f(&mut self.state.lock().unwrap())
+    }
+
+    /// Latch a fault condition in the [FaultStatus] and [DiagnosticStatus] registers.
+    pub fn inject(&self, fault: SimFault) {
+        self.with_state(|s| match fault {
+            SimFault::Ocp(fet) => {
+                s.fault |= OCP;
+                s.diag |= match fet {
+                    Fet::HighSide1 => OCP_H1,
+                    Fet::LowSide1 => OCP_L1,
+                    Fet::HighSide2 => OCP_H2,
+                    Fet::LowSide2 => OCP_L2,
+                };
+            }
+            SimFault::Tsd => s.fault |= TSD,
+            SimFault::Otw => s.fault |= OTW,
+            SimFault::Uvlo => s.fault |= UVLO,
+            SimFault::Cpuv => s.fault |= CPUV,
+            SimFault::OpenLoad(HalfBridge::Out1) => {
+                s.fault |= OLD;
+                s.diag |= OL1;
+            }
+            SimFault::OpenLoad(HalfBridge::Out2) => {
+                s.fault |= OLD;
+                s.diag |= OL2;
+            }
+        })
+    }
+
+    /// Set whether the outputs are currently in ITRIP current regulation.
+    pub fn set_itrip(&self, out1: bool, out2: bool) {
+        self.with_state(|s| {
+            s.diag &= !(ITRIP1 | ITRIP2);
+            if out1 {
+                s.diag |= ITRIP1;
+            }
+            if out2 {
+                s.diag |= ITRIP2;
+            }
+        })
+    }
+
+    /// Disconnect the load from the given outputs, the next standby open-load test started
+    /// through `en_olp` in [ControlRegister4] will report it.
+    pub fn set_open_load(&self, out1: bool, out2: bool) {
+        self.with_state(|s| s.open_load = (out1, out2))
+    }
+
+    /// Raw contents of the register at `addr` as it would be read over SPI.
+    pub fn register(&self, addr: u8) -> u8 {
+        self.with_state(|s| s.read(addr))
+    }
+
+    pub fn fault_status(&self) -> FaultStatus {
+        FaultStatus(self.register(FaultStatus::ADDR))
+    }
+
+    pub fn diagnostic_status(&self) -> DiagnosticStatus {
+        DiagnosticStatus(self.register(DiagnosticStatus::ADDR))
+    }
+
+    /// The control registers as currently stored in the device.
+    pub fn config(&self) -> DRV8873Config {
+        self.with_state(|s| DRV8873Config {
+            cr1: ControlRegister1(s.cr1),
+            cr2: ControlRegister2(s.cr2),
+            cr3: ControlRegister3(s.cr3),
+            cr4: ControlRegister4(s.cr4),
+        })
+    }
+
+    /// Number of complete 16 bit frames the device has received.
+    pub fn frames(&self) -> usize {
+        self.with_state(|s| s.frames)
+    }
+
+    fn run(&mut self, operations: &mut [Operation<'_, u8>]) {
+        self.with_state(|s| {
+            for op in operations.iter_mut() {
+                match op {
+                    Operation::Read(buf) => buf.iter_mut().for_each(|b| *b = s.exchange(0)),
+                    Operation::Write(buf) => buf.iter().for_each(|b| {
+                        s.exchange(*b);
+                    }),
+                    Operation::Transfer(read, write) => {
+                        for i in 0..read.len().max(write.len()) {
+                            let miso = s.exchange(write.get(i).copied().unwrap_or(0));
+                            if let Some(r) = read.get_mut(i) {
+                                *r = miso;
+                            }
+                        }
+                    }
+                    Operation::TransferInPlace(buf) => {
+                        buf.iter_mut().for_each(|b| *b = s.exchange(*b))
+                    }
+                    Operation::DelayNs(_) => {}
+                }
+            }
+            // Deasserting chip select aborts an incomplete frame.
+            s.frame.clear();
+        })
+    }
+}
+
+impl ErrorType for SimDevice {
+    type Error = Infallible;
+}
+
+impl embedded_hal::spi::SpiDevice for SimDevice {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+        self.run(operations);
+        Ok(())
+    }
+}
+
+impl embedded_hal_async::spi::SpiDevice for SimDevice {
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Infallible> {
+        self.run(operations);
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! Peak-and-hold solenoid and valve control.
+//!
+//! A solenoid needs a high current to pull in its armature but only a fraction of that to hold
+//! it. A [Solenoid] drives one output of a device in [Mode::IndependentHalfBridge] fully on for the
+//! pull-in time and then drops to a lower hold level, set by PWM duty and optionally by lowering
+//! the ITRIP level in [ControlRegister4].
+//!
+//! The ITRIP level is shared by both outputs of the device, when two solenoids are connected to
+//! the same DRV8873 only one of them should change it.
+//!
+//! [Mode::IndependentHalfBridge]: crate::registers::Mode::IndependentHalfBridge
+//! [ControlRegister4]: crate::registers::ControlRegister4
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::outputs::HalfBridge;
+use crate::registers::{DisITrip, ITripLvl, Mode};
+use crate::{Drv8873Error, DRV8873};
+
+/// Timing and levels of a peak-and-hold cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolenoidTiming {
+    /// Time the output is fully on to pull in the armature, in microseconds.
+    pub pull_in_us: u32,
+    /// Duty cycle in percent while holding, at most 100.
+    pub hold_duty: u8,
+    /// ITRIP level during pull-in, leaves the current level untouched when [None].
+    pub pull_in_itrip: Option<ITripLvl>,
+    /// ITRIP level while holding, leaves the current level untouched when [None].
+    pub hold_itrip: Option<ITripLvl>,
+}
+
+impl Default for SolenoidTiming {
+    fn default() -> Self {
+        Self {
+            pull_in_us: 50_000,
+            hold_duty: 30,
+            pull_in_itrip: None,
+            hold_itrip: None,
+        }
+    }
+}
+
+/// How the output is switched off when de-energizing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Release {
+    /// Disable the half bridge (Hi-Z), the current decays quickly through the body diodes.
+    Coast,
+    /// Drive the output low, the current recirculates slowly through the low-side FET.
+    Brake,
+}
+
+/// A solenoid connected to a single half bridge.
+pub struct Solenoid<PW: SetDutyCycle> {
+    pwm: PW,
+    output: HalfBridge,
+    timing: SolenoidTiming,
+}
+
+impl<PW: SetDutyCycle> Solenoid<PW> {
+    /// Create a solenoid on `output`, `pwm` drives the matching IN1 or IN2 pin.
+    pub fn new(pwm: PW, output: HalfBridge, timing: SolenoidTiming) -> Self {
+        Self {
+            pwm,
+            output,
+            timing,
+        }
+    }
+    /// Release the PWM channel.
+    pub fn into_inner(self) -> PW {
+        self.pwm
+    }
+    pub fn timing(&self) -> &SolenoidTiming {
+        &self.timing
+    }
+    pub fn set_timing(&mut self, timing: SolenoidTiming) {
+        self.timing = timing;
+    }
+    async fn set_output_disabled<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &self,
+        drv: &mut DRV8873<D, P, L>,
+        disabled: bool,
+    ) -> Result<(), Drv8873Error> {
+        let output = self.output;
+        drv.modify_cr3(|mut cr3| {
+            match output {
+                HalfBridge::Out1 => cr3.set_out1_dis(disabled),
+                HalfBridge::Out2 => cr3.set_out2_dis(disabled),
+            }
+            cr3
+        })
+        .await?;
+        Ok(())
+    }
+    /// Set the ITRIP level and enable current regulation on the output of the solenoid, the
+    /// other output keeps its setting.
+    async fn set_itrip<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &self,
+        drv: &mut DRV8873<D, P, L>,
+        level: Option<ITripLvl>,
+    ) -> Result<(), Drv8873Error> {
+        let output = self.output;
+        if let Some(level) = level {
+            drv.modify_cr4(|mut cr4| {
+                let i_trip = match (output, cr4.i_trip()) {
+                    (HalfBridge::Out1, DisITrip::Disabled) => DisITrip::Out2Disabled,
+                    (HalfBridge::Out2, DisITrip::Disabled) => DisITrip::Out1Disabled,
+                    (HalfBridge::Out1, DisITrip::Out1Disabled)
+                    | (HalfBridge::Out2, DisITrip::Out2Disabled) => DisITrip::Enabled,
+                    (_, i_trip) => i_trip,
+                };
+                cr4.set_i_trip(i_trip);
+                cr4.set_i_trip_lvl(level);
+                cr4
+            })
+            .await?;
+        }
+        Ok(())
+    }
+    /// Put the device in [Mode::IndependentHalfBridge], run the pull-in phase and leave the
+    /// solenoid at the hold level. A `hold_duty` above 100 is rejected with
+    /// [Drv8873Error::InputError] before anything is changed.
+    pub async fn energize<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Drv8873Error> {
+        if self.timing.hold_duty > 100 {
+            return Err(Drv8873Error::InputError(
+                "Hold duty cycle above 100 percent",
+            ));
+        }
+        drv.modify_cr1(|mut cr1| {
+            cr1.set_mode(Mode::IndependentHalfBridge);
+            cr1.set_spi_in(false);
+            cr1
+        })
+        .await?;
+        self.set_itrip(drv, self.timing.pull_in_itrip).await?;
+        self.set_output_disabled(drv, false).await?;
+        self.pwm
+            .set_duty_cycle_fully_on()
+            .map_err(|_| Drv8873Error::InputError("Unable to set solenoid PWM"))?;
+        delay.delay_us(self.timing.pull_in_us).await;
+        self.set_itrip(drv, self.timing.hold_itrip).await?;
+        self.pwm
+            .set_duty_cycle_percent(self.timing.hold_duty)
+            .map_err(|_| Drv8873Error::InputError("Unable to set solenoid PWM"))
+    }
+    /// Switch the solenoid off using the given [Release] mode.
+    pub async fn de_energize<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        release: Release,
+    ) -> Result<(), Drv8873Error> {
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(|_| Drv8873Error::InputError("Unable to set solenoid PWM"))?;
+        self.set_output_disabled(drv, release == Release::Coast)
+            .await
+    }
+}
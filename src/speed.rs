@@ -0,0 +1,101 @@
+//! Closed-loop speed control with encoder feedback.
+//!
+//! A [SpeedController] estimates the motor speed from an [Encoder], runs a [Pid] on the speed
+//! error and drives a [Motor]. While the DRV8873 limits the current through ITRIP regulation
+//! the motor can't follow the controller, so the integral is frozen whenever the
+//! [DiagnosticStatus] reports `itrip1` or `itrip2`.
+//!
+//! [DiagnosticStatus]: crate::registers::DiagnosticStatus
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::encoder::{Encoder, EncoderSample, SpeedEstimator};
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::pid::{Pid, PidGains};
+use crate::ramp::FULL_SCALE;
+use crate::{Drv8873Error, DRV8873};
+
+/// Speed controller for a motor with an encoder.
+pub struct SpeedController<E: Encoder> {
+    encoder: E,
+    estimator: SpeedEstimator,
+    pid: Pid,
+    setpoint: i32,
+    last_timestamp: Option<u32>,
+}
+
+impl<E: Encoder> SpeedController<E> {
+    /// Create a controller, the PID output is a signed Q15 speed.
+    pub fn new(encoder: E, gains: PidGains) -> Self {
+        Self {
+            encoder,
+            estimator: SpeedEstimator::new(),
+            pid: Pid::new(gains, -FULL_SCALE, FULL_SCALE),
+            setpoint: 0,
+            last_timestamp: None,
+        }
+    }
+    /// Release the encoder.
+    pub fn into_inner(self) -> E {
+        self.encoder
+    }
+    pub fn pid(&mut self) -> &mut Pid {
+        &mut self.pid
+    }
+    /// The target speed in counts per second.
+    pub fn setpoint(&self) -> i32 {
+        self.setpoint
+    }
+    pub fn set_setpoint(&mut self, counts_per_second: i32) {
+        self.setpoint = counts_per_second;
+    }
+    /// The last measured speed in counts per second.
+    pub fn speed(&self) -> i32 {
+        self.estimator.speed()
+    }
+    /// Clear the controller state, for example after the motor was stopped outside of the loop.
+    pub fn reset(&mut self) {
+        self.estimator.reset();
+        self.pid.reset();
+        self.last_timestamp = None;
+    }
+    /// Run the controller on an encoder sample and return the Q15 speed to apply.
+    pub fn control(&mut self, sample: EncoderSample, in_regulation: bool) -> i16 {
+        let dt = self
+            .last_timestamp
+            .map_or(0, |last| sample.timestamp_us.wrapping_sub(last));
+        self.last_timestamp = Some(sample.timestamp_us);
+        let speed = self.estimator.update(sample);
+        let error = self.setpoint.saturating_sub(speed);
+        self.pid.update(error, dt, in_regulation) as i16
+    }
+    /// Sample the encoder, check the ITRIP flags of the device and update the motor speed.
+    /// Returns the Q15 speed that was applied.
+    ///
+    /// A change of direction goes through the [ReversalPolicy] of the motor, `delay` is used to
+    /// wait for it.
+    ///
+    /// [ReversalPolicy]: crate::motor::ReversalPolicy
+    pub async fn update<D, P, PW, MP, L>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        delay: &mut impl DelayNs,
+    ) -> Result<i16, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        let in_regulation = drv.read_diagnostics().await?.in_current_regulation();
+        let sample = self.encoder.sample();
+        let speed = self.control(sample, in_regulation);
+        motor.drive_velocity(speed, drv, delay).await?;
+        Ok(speed)
+    }
+}
@@ -0,0 +1,183 @@
+//! Sensorless stall detection.
+//!
+//! A stalled motor draws its locked-rotor current, which either pushes the DRV8873 into ITRIP
+//! regulation or shows up on IPROPI, and an encoder (if there is one) stops counting. A
+//! [StallDetector] requires a high load and, when an encoder is available, a low speed, and
+//! reports a stall once this persisted for a configurable window, so short current peaks while
+//! accelerating don't trigger it.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::{Drv8873Error, DRV8873};
+
+/// What to do with the motor once a stall is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StallAction {
+    /// Only report the stall.
+    None,
+    /// Brake the motor.
+    #[default]
+    Brake,
+    /// Turn the inputs off, see [Motor::coast].
+    Coast,
+}
+
+/// Thresholds of a [StallDetector].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StallConfig {
+    /// How long the stall indications have to persist in microseconds.
+    pub window_us: u32,
+    /// Whether ITRIP regulation on either output indicates a stall.
+    pub use_itrip: bool,
+    /// A load current at or above this many milliamps indicates a stall.
+    pub current_ma: Option<u32>,
+    /// When set, a stall also requires a measured speed below this many counts per second.
+    pub min_speed: Option<u32>,
+    pub action: StallAction,
+}
+
+impl Default for StallConfig {
+    fn default() -> Self {
+        Self {
+            window_us: 50_000,
+            use_itrip: true,
+            current_ma: None,
+            min_speed: None,
+            action: StallAction::Brake,
+        }
+    }
+}
+
+/// The measurements a [StallDetector] decides on, [None] when not available.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StallInputs {
+    /// Either output is in ITRIP regulation.
+    pub in_regulation: bool,
+    /// Load current in milliamps, for example from [IpropiScale](crate::current::IpropiScale).
+    pub current_ma: Option<u32>,
+    /// Speed in counts per second, for example from
+    /// [SpeedEstimator](crate::encoder::SpeedEstimator).
+    pub speed: Option<i32>,
+}
+
+/// Reported once when a stall is detected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StallEvent {
+    /// How long the stall indications persisted in microseconds.
+    pub duration_us: u32,
+    /// The measurements that completed the window.
+    pub inputs: StallInputs,
+    /// The action that was applied to the motor.
+    pub action: StallAction,
+}
+
+/// Detects a stalled motor from ITRIP flags, current and speed.
+#[derive(Debug, Clone)]
+pub struct StallDetector {
+    config: StallConfig,
+    stalled_for: u32,
+    stalled: bool,
+}
+
+impl StallDetector {
+    pub fn new(config: StallConfig) -> Self {
+        Self {
+            config,
+            stalled_for: 0,
+            stalled: false,
+        }
+    }
+    pub fn config(&self) -> &StallConfig {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: StallConfig) {
+        self.config = config;
+    }
+    /// Whether a stall was detected since the last [StallDetector::reset].
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+    /// Clear the window and the detected stall.
+    pub fn reset(&mut self) {
+        self.stalled_for = 0;
+        self.stalled = false;
+    }
+    /// Whether `inputs` indicate a stall on their own.
+    ///
+    /// A stall needs a high load, ITRIP regulation or a current above the threshold. When a
+    /// minimum speed is configured and a speed is measured, the motor also has to be slower than
+    /// that, so a motor that's just spinning up or ramping slowly isn't reported.
+    pub fn indicates_stall(&self, inputs: &StallInputs) -> bool {
+        let itrip = self.config.use_itrip && inputs.in_regulation;
+        let current = matches!(
+            (self.config.current_ma, inputs.current_ma),
+            (Some(limit), Some(current)) if current >= limit
+        );
+        let slow = match (self.config.min_speed, inputs.speed) {
+            (Some(limit), Some(speed)) => speed.unsigned_abs() < limit,
+            _ => true,
+        };
+        (itrip || current) && slow
+    }
+    /// Add measurements taken `dt_us` microseconds after the previous ones. Returns an event the
+    /// first time the stall indications persisted for the configured window, no action is
+    /// applied.
+    pub fn update(&mut self, inputs: StallInputs, dt_us: u32) -> Option<StallEvent> {
+        if !self.indicates_stall(&inputs) {
+            self.stalled_for = 0;
+            return None;
+        }
+        self.stalled_for = self.stalled_for.saturating_add(dt_us);
+        if self.stalled || self.stalled_for < self.config.window_us {
+            return None;
+        }
+        self.stalled = true;
+        Some(StallEvent {
+            duration_us: self.stalled_for,
+            inputs,
+            action: StallAction::None,
+        })
+    }
+    /// Read the ITRIP flags from the device, combine them with the optional current and speed
+    /// and apply the configured [StallAction] when a stall is detected.
+    ///
+    /// The window only runs while the motor is commanded to move.
+    pub async fn check<D, P, PW, MP, L>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+        current_ma: Option<u32>,
+        speed: Option<i32>,
+        dt_us: u32,
+    ) -> Result<Option<StallEvent>, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        if motor.velocity() == 0 {
+            self.stalled_for = 0;
+            return Ok(None);
+        }
+        let inputs = StallInputs {
+            in_regulation: drv.read_diagnostics().await?.in_current_regulation(),
+            current_ma,
+            speed,
+        };
+        let Some(mut event) = self.update(inputs, dt_us) else {
+            return Ok(None);
+        };
+        match self.config.action {
+            StallAction::None => {}
+            StallAction::Brake => motor.brake()?,
+            StallAction::Coast => motor.coast()?,
+        }
+        event.action = self.config.action;
+        Ok(Some(event))
+    }
+}
@@ -0,0 +1,276 @@
+//! Bipolar stepper motor driven by two DRV8873 H-bridges.
+//!
+//! Each coil of the stepper is connected to the outputs of its own DRV8873 and driven as a
+//! [Motor], the sign of the duty cycle sets the direction of the coil current. The electrical
+//! angle is tracked in 1/32 of a full step, full and half steps drive the coils at full duty and
+//! microsteps follow a sine table. The coil current is set by the supply voltage, the winding
+//! resistance and the duty cycle, use the ITRIP level to limit it.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::ramp::isqrt;
+use crate::registers::DiagnosticStatus;
+use crate::{Drv8873Error, DRV8873};
+
+/// Electrical angle steps per full step.
+const PHASE_PER_STEP: u8 = 32;
+/// Electrical angle steps per electrical revolution, four full steps.
+const PHASE_PER_REV: u8 = 4 * PHASE_PER_STEP;
+
+/// A quarter sine wave in Q15, from 0 to 90 degrees in 1/32 of a full step.
+const SINE: [i16; 33] = [
+    0, 1608, 3212, 4808, 6393, 7962, 9512, 11039, 12539, 14010, 15446, 16846, 18204, 19519, 20787,
+    22005, 23170, 24279, 25329, 26319, 27245, 28105, 28898, 29621, 30273, 30852, 31356, 31785,
+    32137, 32412, 32609, 32728, 32767,
+];
+
+/// One of the two coils of a stepper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coil {
+    A,
+    B,
+}
+
+/// An error of the device or the inputs of a single coil.
+#[derive(core::fmt::Debug)]
+pub struct StepperError {
+    pub coil: Coil,
+    pub error: Drv8873Error,
+}
+
+/// The size of a single step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StepMode {
+    /// Both coils always on.
+    #[default]
+    Full,
+    /// Alternating one and both coils on.
+    Half,
+    /// Sine wave microstepping with 4 steps per full step.
+    Quarter,
+    /// Sine wave microstepping with 8 steps per full step.
+    Eighth,
+    /// Sine wave microstepping with 16 steps per full step.
+    Sixteenth,
+    /// Sine wave microstepping with 32 steps per full step.
+    ThirtySecond,
+}
+
+impl StepMode {
+    /// Steps per full step.
+    pub fn divisions(&self) -> u8 {
+        match self {
+            StepMode::Full => 1,
+            StepMode::Half => 2,
+            StepMode::Quarter => 4,
+            StepMode::Eighth => 8,
+            StepMode::Sixteenth => 16,
+            StepMode::ThirtySecond => 32,
+        }
+    }
+    /// The signed Q15 duty cycles of coil A and B at `position` steps.
+    pub fn coil_duty(&self, position: i32) -> (i16, i16) {
+        let increment = (PHASE_PER_STEP / self.divisions()) as i32;
+        // Position zero is a full step, with both coils on.
+        let phase = (PHASE_PER_STEP as i32 / 2)
+            .wrapping_add(position.wrapping_mul(increment))
+            .rem_euclid(PHASE_PER_REV as i32) as u8;
+        let (cos, sin) = (sine(phase + PHASE_PER_STEP), sine(phase));
+        match self {
+            StepMode::Full | StepMode::Half => (cos.signum() * i16::MAX, sin.signum() * i16::MAX),
+            _ => (cos, sin),
+        }
+    }
+}
+
+/// Sine of an electrical angle in 1/32 of a full step, in Q15.
+fn sine(phase: u8) -> i16 {
+    let phase = phase % PHASE_PER_REV;
+    let (quadrant, offset) = (phase / PHASE_PER_STEP, (phase % PHASE_PER_STEP) as usize);
+    match quadrant {
+        0 => SINE[offset],
+        1 => SINE[PHASE_PER_STEP as usize - offset],
+        2 => -SINE[offset],
+        _ => -SINE[PHASE_PER_STEP as usize - offset],
+    }
+}
+
+/// Step mode and motion limits of a [Stepper].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepperConfig {
+    pub mode: StepMode,
+    /// Step rate in steps per second at the start and the end of a move.
+    pub start_rate: u32,
+    /// Maximum step rate in steps per second.
+    pub max_rate: u32,
+    /// Acceleration and deceleration in steps per second squared, zero moves at the maximum
+    /// rate.
+    pub acceleration: u32,
+}
+
+impl Default for StepperConfig {
+    fn default() -> Self {
+        Self {
+            mode: StepMode::Full,
+            start_rate: 100,
+            max_rate: 1_000,
+            acceleration: 2_000,
+        }
+    }
+}
+
+/// The device driving a coil and the [Motor] connected to its outputs.
+pub type StepperCoil<D, P, PW, MP, L = ()> = (DRV8873<D, P, L>, Motor<PW, MP>);
+
+/// A bipolar stepper motor with each coil on its own DRV8873.
+pub struct Stepper<D, P, PW, MP, L = ()>
+where
+    D: SpiDevice,
+    P: StatefulOutputPin,
+    PW: SetDutyCycle,
+    MP: StatefulOutputPin,
+    L: FaultSink,
+{
+    coils: [StepperCoil<D, P, PW, MP, L>; 2],
+    config: StepperConfig,
+    position: i32,
+}
+
+impl<D, P, PW, MP, L> Stepper<D, P, PW, MP, L>
+where
+    D: SpiDevice,
+    P: StatefulOutputPin,
+    PW: SetDutyCycle,
+    MP: StatefulOutputPin,
+    L: FaultSink,
+{
+    /// Create a stepper from the device and inputs of coil A and coil B. The coils stay off
+    /// until the first step or [Stepper::energize].
+    pub fn new(coils: [StepperCoil<D, P, PW, MP, L>; 2], config: StepperConfig) -> Self {
+        Self {
+            coils,
+            config,
+            position: 0,
+        }
+    }
+    /// Release the device and inputs of coil A and coil B.
+    pub fn into_inner(self) -> [StepperCoil<D, P, PW, MP, L>; 2] {
+        self.coils
+    }
+    /// Get the device of a single coil.
+    pub fn device(&mut self, coil: Coil) -> &mut DRV8873<D, P, L> {
+        &mut self.coils[coil as usize].0
+    }
+    pub fn config(&self) -> &StepperConfig {
+        &self.config
+    }
+    /// Change the configuration, the position is converted to the new step mode and rounded
+    /// down to a whole step. The coils are updated on the next step.
+    pub fn set_config(&mut self, config: StepperConfig) {
+        let (old, new) = (self.config.mode.divisions(), config.mode.divisions());
+        self.position = (self.position as i64 * new as i64).div_euclid(old as i64) as i32;
+        self.config = config;
+    }
+    /// The position in steps of the current [StepMode].
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+    /// Define the current position without moving.
+    pub fn set_position(&mut self, position: i32) {
+        self.position = position;
+    }
+    /// Drive the coils for the current position, the duty of each coil is passed to the fault
+    /// log of its device as the velocity.
+    pub fn energize(&mut self) -> Result<(), StepperError> {
+        let (a, b) = self.config.mode.coil_duty(self.position);
+        for (coil, ((drv, motor), duty)) in [Coil::A, Coil::B]
+            .into_iter()
+            .zip(self.coils.iter_mut().zip([a, b]))
+        {
+            motor
+                .apply_velocity(duty)
+                .map_err(|error| StepperError { coil, error })?;
+            drv.fault_log_mut().velocity(duty);
+        }
+        Ok(())
+    }
+    /// Turn both coils off, the motor no longer holds its position.
+    pub fn de_energize(&mut self) -> Result<(), StepperError> {
+        for (coil, (_, motor)) in [Coil::A, Coil::B].into_iter().zip(self.coils.iter_mut()) {
+            motor
+                .coast()
+                .map_err(|error| StepperError { coil, error })?;
+        }
+        Ok(())
+    }
+    /// Move a single step forward or backward.
+    pub fn step(&mut self, forward: bool) -> Result<(), StepperError> {
+        self.position = self.position.wrapping_add(if forward { 1 } else { -1 });
+        self.energize()
+    }
+    /// Read the [DiagnosticStatus] of both coils.
+    ///
+    /// A coil with an overcurrent or open-load flag fails with [Drv8873Error::Drv8873Fault]
+    /// holding the [FaultStatus](crate::registers::FaultStatus) of its device.
+    pub async fn check_faults(&mut self) -> Result<[DiagnosticStatus; 2], StepperError> {
+        let mut status: [DiagnosticStatus; 2] = Default::default();
+        for (coil, ((dev, _), status)) in [Coil::A, Coil::B]
+            .into_iter()
+            .zip(self.coils.iter_mut().zip(status.iter_mut()))
+        {
+            let diagnostics = dev
+                .read_diagnostics()
+                .await
+                .map_err(|error| StepperError { coil, error })?;
+            if diagnostics.out1_fault() || diagnostics.out2_fault() {
+                let fault = dev
+                    .read_fault()
+                    .await
+                    .map_err(|error| StepperError { coil, error })?;
+                return Err(StepperError {
+                    coil,
+                    error: Drv8873Error::Drv8873Fault(fault),
+                });
+            }
+            *status = diagnostics;
+        }
+        Ok(status)
+    }
+    /// Move `steps` steps with a trapezoidal rate profile and return the new position.
+    ///
+    /// The faults of both coils are checked after every step, the move stops at the first fault
+    /// with the coils still energized.
+    pub async fn move_steps(
+        &mut self,
+        steps: i32,
+        delay: &mut impl DelayNs,
+    ) -> Result<i32, StepperError> {
+        let config = self.config;
+        let max_rate = config.max_rate.max(1) as u64;
+        let start_rate = (config.start_rate as u64).clamp(1, max_rate);
+        let two_accel = 2 * config.acceleration as u64;
+        let mut rate = if two_accel == 0 { max_rate } else { start_rate };
+
+        let count = steps.unsigned_abs();
+        for done in 1..=count {
+            self.step(steps > 0)?;
+            self.check_faults().await?;
+            delay.delay_us((1_000_000 / rate) as u32).await;
+            if two_accel == 0 {
+                continue;
+            }
+            let remaining = (count - done) as u64;
+            let braking_steps = (rate * rate - start_rate * start_rate) / two_accel;
+            rate = if remaining <= braking_steps {
+                isqrt((rate * rate).saturating_sub(two_accel)).max(start_rate)
+            } else {
+                isqrt(rate * rate + two_accel).min(max_rate)
+            };
+        }
+        Ok(self.position)
+    }
+}
@@ -4,14 +4,125 @@
 use super::*;
 use registers::ReadableRegister;
 
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// A PWM channel that records its duty cycle, clones share the same duty cycle.
+#[derive(Clone, Default)]
+struct TestPwm(Rc<Cell<u16>>);
+impl TestPwm {
+    const MAX: u16 = 1000;
+    fn duty(&self) -> u16 {
+        self.0.get()
+    }
+}
+impl embedded_hal::pwm::ErrorType for TestPwm {
+    type Error = core::convert::Infallible;
+}
+impl embedded_hal::pwm::SetDutyCycle for TestPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        Self::MAX
+    }
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.0.set(duty);
+        Ok(())
+    }
+}
+/// A delay that only advances a shared clock, clones share the same clock.
+#[derive(Clone, Default)]
+struct TestDelay(Rc<Cell<u64>>);
+impl TestDelay {
+    fn elapsed_us(&self) -> u64 {
+        self.0.get() / 1000
+    }
+}
+impl embedded_hal_async::delay::DelayNs for TestDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.0.set(self.0.get() + ns as u64);
+    }
+}
+impl clock::Clock for TestDelay {
+    fn now_us(&mut self) -> u64 {
+        self.elapsed_us()
+    }
+}
+/// An encoder whose count and timestamp are set by the test, clones share the same state.
+#[derive(Clone, Default)]
+struct TestEncoder(Rc<Cell<encoder::EncoderSample>>);
+impl TestEncoder {
+    /// Advance time by `dt_us` with the motor running at `speed` counts per second.
+    fn run(&self, speed: i32, dt_us: u32) {
+        let mut sample = self.0.get();
+        sample.count += (speed as i64 * dt_us as i64 / 1_000_000) as i32;
+        sample.timestamp_us += dt_us;
+        self.0.set(sample);
+    }
+}
+impl encoder::Encoder for TestEncoder {
+    fn sample(&mut self) -> encoder::EncoderSample {
+        self.0.get()
+    }
+}
+/// A delay that runs a motor model while time passes. The motor is driven in PWM mode and runs
+/// at 10000 counts per second at full duty forward, unless it's stalled.
+#[derive(Clone, Default)]
+struct PlantDelay {
+    delay: TestDelay,
+    encoder: TestEncoder,
+    in1: TestPwm,
+    in2: TestPwm,
+    stalled: Rc<Cell<bool>>,
+}
+impl PlantDelay {
+    fn motor(&self) -> Motor<TestPwm, TestPin> {
+        Motor::new(InputMode::PWM(Some((self.in1.clone(), self.in2.clone()))))
+    }
+}
+impl embedded_hal_async::delay::DelayNs for PlantDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        let speed = if self.stalled.get() {
+            0
+        } else {
+            (self.in1.duty() as i32 - self.in2.duty() as i32) * 10
+        };
+        self.encoder.run(speed, ns / 1000);
+        self.delay.delay_ns(ns).await;
+    }
+}
+/// An output pin that records its level, clones share the same level.
+#[derive(Clone, Default)]
+struct TestPin(Rc<Cell<bool>>);
+impl TestPin {
+    fn is_high(&self) -> bool {
+        self.0.get()
+    }
+}
+impl embedded_hal::digital::ErrorType for TestPin {
+    type Error = core::convert::Infallible;
+}
+impl embedded_hal::digital::OutputPin for TestPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.set(false);
+        Ok(())
+    }
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+impl embedded_hal::digital::StatefulOutputPin for TestPin {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.0.get())
+    }
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.0.get())
+    }
+}
+
 mod bar {
     use super::*;
     use embedded_hal_mock::common::Generic;
-    use embedded_hal_mock::eh1::delay::NoopDelay;
-    use embedded_hal_mock::eh1::digital::{
-        Mock as WaitMock, State, Transaction as WaitTransaction,
-    };
-    use embedded_hal_mock::eh1::pwm::{Mock as PwmMock, Transaction as PwmTransaction};
+    use embedded_hal_mock::eh1::digital::Mock as WaitMock;
     use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
     #[async_std::test]
     async fn registers() {
@@ -65,39 +176,37 @@ mod bar {
             SpiTransaction::transfer(vec![0b01000010, 0b00000000], vec![0b11000000, 0b10000000]),
             SpiTransaction::transaction_end(),
             SpiTransaction::transaction_start(),
-            SpiTransaction::transfer(
-                vec![
-                    0b00000100,
-                    ControlRegister1::default().0,
-                    0b00000110,
-                    ControlRegister2::default().0,
-                    0b00001000,
-                    ControlRegister3::default().0,
-                    0b00001010,
-                    ControlRegister4::default().0,
-                ],
-                vec![
-                    0b11000000, 0b10000000, 0b11000000, 0b10000000, 0b11000000, 0b10000000,
-                    0b11000000, 0b10000000,
-                ],
+            SpiTransaction::transfer_in_place(
+                vec![0b00000100, ControlRegister1::default().0],
+                vec![0b11000000, 0b10000000],
+            ),
+            SpiTransaction::transaction_end(),
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(
+                vec![0b00000110, ControlRegister2::default().0],
+                vec![0b11000000, 0b10000000],
+            ),
+            SpiTransaction::transaction_end(),
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(
+                vec![0b00001000, ControlRegister3::default().0],
+                vec![0b11000000, 0b10000000],
+            ),
+            SpiTransaction::transaction_end(),
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(
+                vec![0b00001010, ControlRegister4::default().0],
+                vec![0b11000000, 0b10000000],
             ),
             SpiTransaction::transaction_end(),
         ];
 
         let fault_expectation = [];
-        let sleep_expectation = [
-            WaitTransaction::get_state(State::Low),
-            WaitTransaction::set(State::High),
-            WaitTransaction::get_state(State::High),
-            WaitTransaction::set(State::Low),
-        ];
         let mut spi = SpiMock::new(&expectations);
         let mut fault = WaitMock::new(fault_expectation);
         // let mut sleep = WaitMock::new(&sleep_expectation);
-        let mut delay = NoopDelay::new();
         let cfg = DRV8873Config::default();
-        let mut dev: DRV8873<Generic<SpiTransaction<u8>>, WaitMock, WaitMock, NoopDelay> =
-            DRV8873::new(spi.clone());
+        let mut dev: DRV8873<Generic<SpiTransaction<u8>>, WaitMock> = DRV8873::new(spi.clone());
         dev.read_fault().await.unwrap();
         dev.read_diagnostics().await.unwrap();
         dev.write_config(&cfg).await.unwrap();
@@ -128,3 +237,1576 @@ fn control_register1() {
 }
 #[test]
 fn control_register2() {}
+#[test]
+fn capture_frames() {
+    use capture::{parse_csv, parse_frame, Frame};
+    let frame = parse_frame("4200/c080").unwrap();
+    assert_eq!(
+        frame,
+        Frame {
+            mosi: [0x42, 0x00],
+            miso: Some([0xc0, 0x80]),
+        }
+    );
+    let command = frame.command();
+    assert!(command.read && !command.reserved_bits);
+    assert_eq!(command.address, DiagnosticStatus::ADDR);
+    let status = frame.status().unwrap();
+    assert!(status.framing_ok && status.fault.is_none());
+
+    let frame = parse_frame(" 0x0482 ").unwrap_err();
+    assert!(frame.contains("expected two hex bytes"));
+    let frame = parse_frame("0482").unwrap();
+    assert!(!frame.command().read && frame.status().is_none());
+    assert!(parse_frame("04g2")
+        .unwrap_err()
+        .contains("invalid hex byte"));
+    assert!(parse_frame("048/c080").is_err());
+    assert!(parse_frame("0482/c08").is_err());
+    assert!(parse_frame("0482/c080/00").is_err());
+    assert!(parse_frame("8483").unwrap().command().reserved_bits);
+
+    // An undervoltage fault with a broken framing bit.
+    let status = parse_frame("4000/9000").unwrap().status().unwrap();
+    assert!(!status.framing_ok);
+    assert!(status.fault.unwrap().uvlo());
+    let status = parse_frame("4000/d100").unwrap().status().unwrap();
+    assert!(status.framing_ok);
+    let fault = status.fault.unwrap();
+    assert!(fault.uvlo() && fault.old() && !fault.ocp());
+
+    let csv = "Time,\"MOSI\",MISO\n0.1,0x42,0xC0\n0.2,0x00,0x80\n\n0.3,04,C0\n0.4,82,00\n";
+    let frames = parse_csv(csv).unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].miso, Some([0xc0, 0x80]));
+    assert_eq!(frames[1].mosi, [0x04, 0x82]);
+    let frames = parse_csv("mosi\n42\n00\n").unwrap();
+    assert_eq!(frames[0].miso, None);
+    assert!(parse_csv("").unwrap_err().contains("empty"));
+    assert!(parse_csv("Time,MISO\n0,00\n")
+        .unwrap_err()
+        .contains("no MOSI"));
+    assert!(parse_csv("MOSI,MISO\n42,C0\n00\n")
+        .unwrap_err()
+        .contains("missing column"));
+    assert!(parse_csv("MOSI\n42\n").unwrap_err().contains("odd number"));
+    assert!(parse_csv("MOSI\nzz\n00\n").is_err());
+}
+#[test]
+fn config_text_round_trip() {
+    let cfg = DRV8873Config::default();
+    let text = format!("{}", cfg);
+    assert!(text.starts_with("mode=pwm sr=10.8V/us toff=40us "));
+    assert!(text.contains("ocp=latched"));
+    assert!(text.contains("itrip=6.5A"));
+    assert_eq!(text.parse::<DRV8873Config>(), Ok(cfg));
+
+    let mut cfg = DRV8873Config::default();
+    cfg.cr1.set_mode(Mode::IndependentHalfBridge);
+    cfg.cr2.set_ocp_t_retry(OcpTRetry::Ms0_5);
+    cfg.cr3.set_out2_dis(true);
+    cfg.cr4.set_i_trip(DisITrip::Out1Disabled);
+    let text = format!("{}", cfg);
+    assert_eq!(text.parse::<DRV8873Config>(), Ok(cfg));
+
+    // Lock values other than the two sequences are kept as raw values.
+    let mut cfg = DRV8873Config::default();
+    cfg.cr3.0 = cfg.cr3.0 & !0b0111_0000 | 0b010 << 4;
+    let text = format!("{}", cfg);
+    assert!(text.contains(" lock=2 "));
+    assert_eq!(text.parse::<DRV8873Config>(), Ok(cfg));
+    assert!("lock=locked".parse::<DRV8873Config>().is_ok());
+    assert!("lock=8".parse::<DRV8873Config>().is_err());
+}
+#[test]
+fn config_text_parse() {
+    let cfg: DRV8873Config = "itrip=4A  mode=ph-en\tsr=2.6V/us".parse().unwrap();
+    assert_eq!(cfg.cr1.mode(), Mode::PhaseEnable);
+    assert_eq!(cfg.cr1.sr(), RiseTime::VoltPerUs2_6);
+    assert_eq!(cfg.cr4.i_trip_lvl(), ITripLvl::Ampere4);
+    assert_eq!(cfg.cr2.0, DRV8873Config::default().cr2.0);
+
+    use config::ParseConfigError;
+    assert_eq!(
+        "mode=pwm foo=1".parse::<DRV8873Config>(),
+        Err(ParseConfigError::UnknownKey)
+    );
+    assert_eq!(
+        "sr=11V/us".parse::<DRV8873Config>(),
+        Err(ParseConfigError::InvalidValue("sr"))
+    );
+    assert_eq!(
+        "spi_in=true".parse::<DRV8873Config>(),
+        Err(ParseConfigError::InvalidValue("spi_in"))
+    );
+    assert_eq!(
+        "mode".parse::<DRV8873Config>(),
+        Err(ParseConfigError::MissingValue)
+    );
+}
+mod simulated {
+    use super::*;
+    use crate::outputs::HalfBridge;
+    use crate::sim::{Fet, SimDevice, SimFault};
+    use embedded_hal::spi::SpiDevice as BlockingSpiDevice;
+    use embedded_hal_mock::eh1::digital::Mock as PinMock;
+
+    #[async_std::test]
+    async fn reset_values_and_config() {
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        assert_eq!(dev.read_config().await.unwrap(), DRV8873Config::default());
+
+        let mut cfg = DRV8873Config::default();
+        cfg.cr1.set_mode(Mode::PhaseEnable);
+        cfg.cr4.set_i_trip_lvl(ITripLvl::Ampere4);
+        dev.write_config(&cfg).await.unwrap();
+        assert_eq!(sim.config(), cfg);
+        assert_eq!(dev.read_config().await.unwrap(), cfg);
+    }
+    #[async_std::test]
+    async fn lock() {
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        dev.modify_cr3(|mut cr3| {
+            cr3.set_lock(Lock::Locked);
+            cr3
+        })
+        .await
+        .unwrap();
+        dev.modify_cr1(|mut cr1| {
+            cr1.set_mode(Mode::InputDisabled);
+            cr1
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_cr1().await.unwrap().mode(), Mode::PWM);
+
+        dev.modify_cr3(|mut cr3| {
+            cr3.set_lock(Lock::Unlocked);
+            cr3
+        })
+        .await
+        .unwrap();
+        dev.modify_cr1(|mut cr1| {
+            cr1.set_mode(Mode::InputDisabled);
+            cr1
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_cr1().await.unwrap().mode(), Mode::InputDisabled);
+    }
+    #[async_std::test]
+    async fn faults() {
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        sim.inject(SimFault::Ocp(Fet::LowSide2));
+        let fault = dev.read_fault().await.unwrap();
+        assert!(fault.ocp() && fault.fault());
+        assert!(dev.read_diagnostics().await.unwrap().ocp_l2());
+
+        dev.clear_fault().await.unwrap();
+        assert!(!dev.read_fault().await.unwrap().fault());
+        assert!(!dev.read_cr3().await.unwrap().clr_flt());
+
+        // OTW only sets the global fault bit when it is reported.
+        sim.inject(SimFault::Otw);
+        let fault = dev.read_fault().await.unwrap();
+        assert!(fault.otw() && !fault.fault());
+    }
+    #[async_std::test]
+    async fn open_load_test() {
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        sim.set_open_load(false, true);
+        dev.modify_cr4(|mut cr4| {
+            cr4.set_en_olp(true);
+            cr4
+        })
+        .await
+        .unwrap();
+        assert!(!dev.read_cr4().await.unwrap().en_olp());
+        let diag = dev.read_diagnostics().await.unwrap();
+        assert!(diag.ol2() && !diag.ol1());
+        assert!(sim.fault_status().old());
+        sim.inject(SimFault::OpenLoad(HalfBridge::Out1));
+        assert!(sim.diagnostic_status().ol1());
+    }
+    #[async_std::test]
+    async fn array() {
+        let sims = [SimDevice::new(), SimDevice::new(), SimDevice::new()];
+        let mut array: DRV8873Array<SimDevice, PinMock, 3> =
+            DRV8873Array::new(sims.clone().map(DRV8873::new));
+        let mut cfg = DRV8873Config::default();
+        cfg.cr2.set_itrip_rep(true);
+        array.write_config(&cfg).await.unwrap();
+        assert!(sims.iter().all(|sim| sim.config() == cfg));
+
+        sims[1].inject(SimFault::Tsd);
+        assert_eq!(array.faulted().await.unwrap(), [false, true, false]);
+        let status = array.read_status().await;
+        assert!(status[1].as_ref().unwrap().fault.tsd());
+        assert!(!status[0].as_ref().unwrap().fault.fault());
+        sims[2].set_itrip(true, false);
+        assert!(array.read_faults().await[2].as_ref().unwrap().fault());
+
+        sims[2].set_itrip(false, false);
+        array.clear_faults().await.unwrap();
+        assert_eq!(array.faulted().await.unwrap(), [false; 3]);
+    }
+    #[async_std::test]
+    async fn parallel_output() {
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let (in1, in2) = (TestPwm::default(), TestPwm::default());
+        let mut out = ParallelOutput::new(in1.clone(), in2.clone());
+        out.configure(&mut dev, ITripLvl::Ampere5_4).await.unwrap();
+        let cfg = sim.config();
+        assert_eq!(cfg.cr1.mode(), Mode::IndependentHalfBridge);
+        assert_eq!(cfg.cr4.i_trip(), DisITrip::Enabled);
+        assert_eq!(cfg.cr4.i_trip_lvl(), ITripLvl::Ampere5_4);
+
+        out.set_duty_cycle_percent(30).unwrap();
+        assert_eq!((in1.duty(), in2.duty()), (300, 300));
+        out.set_duty_cycle_percent(150).unwrap();
+        assert_eq!((in1.duty(), in2.duty()), (1000, 1000));
+        out.set_duty_cycle_fraction(5, 4).unwrap();
+        assert_eq!((in1.duty(), in2.duty()), (1000, 1000));
+        assert!(matches!(
+            out.set_duty_cycle_fraction(1, 0),
+            Err(Drv8873Error::InputError(_))
+        ));
+        out.off().unwrap();
+        assert_eq!((in1.duty(), in2.duty()), (0, 0));
+
+        sim.set_itrip(false, true);
+        assert!(out.check_fault(&mut dev).await.unwrap().itrip2());
+        sim.inject(SimFault::Ocp(Fet::HighSide2));
+        match out.check_fault(&mut dev).await {
+            Err(Drv8873Error::Drv8873Fault(fault)) => assert!(fault.ocp()),
+            _ => panic!(),
+        }
+    }
+    #[async_std::test]
+    async fn solenoid() {
+        use crate::solenoid::{Release, Solenoid, SolenoidTiming};
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let mut delay = TestDelay::default();
+        let pwm = TestPwm::default();
+        let timing = SolenoidTiming {
+            pull_in_us: 20_000,
+            hold_duty: 25,
+            pull_in_itrip: Some(ITripLvl::Ampere7),
+            hold_itrip: Some(ITripLvl::Ampere4),
+        };
+        let mut solenoid = Solenoid::new(pwm.clone(), HalfBridge::Out2, timing);
+        solenoid
+            .de_energize(&mut dev, Release::Coast)
+            .await
+            .unwrap();
+        assert!(sim.config().cr3.out2_dis());
+
+        // ITRIP is enabled on OUT2 only, the device is switched to independent half-bridges.
+        dev.modify_cr4(|mut cr4| {
+            cr4.set_i_trip(DisITrip::Disabled);
+            cr4
+        })
+        .await
+        .unwrap();
+        solenoid.energize(&mut dev, &mut delay).await.unwrap();
+        assert_eq!(delay.elapsed_us(), 20_000);
+        assert_eq!(pwm.duty(), 250);
+        let cfg = sim.config();
+        assert!(!cfg.cr3.out2_dis());
+        assert_eq!(cfg.cr1.mode(), Mode::IndependentHalfBridge);
+        assert_eq!(cfg.cr4.i_trip(), DisITrip::Out1Disabled);
+        assert_eq!(cfg.cr4.i_trip_lvl(), ITripLvl::Ampere4);
+
+        solenoid.set_timing(SolenoidTiming {
+            hold_duty: 101,
+            ..timing
+        });
+        assert!(matches!(
+            solenoid.energize(&mut dev, &mut delay).await,
+            Err(Drv8873Error::InputError(_))
+        ));
+        assert_eq!(delay.elapsed_us(), 20_000);
+
+        solenoid
+            .de_energize(&mut dev, Release::Brake)
+            .await
+            .unwrap();
+        assert_eq!(pwm.duty(), 0);
+        assert!(!sim.config().cr3.out2_dis());
+    }
+    #[async_std::test]
+    async fn latching_actuator() {
+        use crate::latch::{LatchConfig, LatchingActuator};
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let mut delay = TestDelay::default();
+        let (in1, in2) = (TestPwm::default(), TestPwm::default());
+        let config = LatchConfig {
+            itrip: Some(ITripLvl::Ampere4),
+            min_rest_us: 500,
+        };
+        let mut latch = LatchingActuator::new(in1.clone(), in2.clone(), config);
+        latch.configure(&mut dev).await.unwrap();
+        assert_eq!(sim.config().cr4.i_trip_lvl(), ITripLvl::Ampere4);
+
+        // With itrip_rep set, ITRIP regulation also sets the FAULT bit but the pulse succeeds.
+        dev.modify_cr2(|mut cr2| {
+            cr2.set_itrip_rep(true);
+            cr2
+        })
+        .await
+        .unwrap();
+        sim.set_itrip(true, false);
+        let diag = latch
+            .pulse_forward(&mut dev, &mut delay, 2_000)
+            .await
+            .unwrap();
+        assert!(diag.itrip1() && dev.read_fault().await.unwrap().fault());
+        assert_eq!((in1.duty(), in2.duty()), (0, 0));
+        assert_eq!(delay.elapsed_us(), 2_500);
+
+        sim.inject(SimFault::Ocp(Fet::HighSide2));
+        assert!(latch
+            .pulse_reverse(&mut dev, &mut delay, 1_000)
+            .await
+            .is_err());
+        assert_eq!((in1.duty(), in2.duty()), (0, 0));
+        assert_eq!(delay.elapsed_us(), 4_000);
+    }
+    #[async_std::test]
+    async fn speed_control() {
+        use crate::pid::{PidGains, ONE};
+        use crate::speed::SpeedController;
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let (in1, in2) = (TestPwm::default(), TestPwm::default());
+        let mut motor: Motor<TestPwm, TestPin> =
+            Motor::new(InputMode::PWM(Some((in1.clone(), in2.clone()))));
+        let encoder = TestEncoder::default();
+        let gains = PidGains {
+            kp: ONE,
+            ki: 20 * ONE,
+            kd: 0,
+        };
+        let mut delay = TestDelay::default();
+        let mut controller = SpeedController::new(encoder.clone(), gains);
+        controller.set_setpoint(4_000);
+        // Full duty runs the motor at 10000 counts per second.
+        let plant = |in1: &TestPwm, in2: &TestPwm| (in1.duty() as i32 - in2.duty() as i32) * 10;
+        for _ in 0..2000 {
+            controller
+                .update(&mut dev, &mut motor, &mut delay)
+                .await
+                .unwrap();
+            encoder.run(plant(&in1, &in2), 1000);
+        }
+        assert_eq!(motor.speed(), 40);
+        assert!((controller.speed() - 4_000).abs() <= 100);
+
+        // In ITRIP regulation the integral is frozen, the output only follows the P term.
+        sim.set_itrip(true, false);
+        encoder.run(0, 1000);
+        controller
+            .update(&mut dev, &mut motor, &mut delay)
+            .await
+            .unwrap();
+        encoder.run(0, 1000);
+        let stalled = controller
+            .update(&mut dev, &mut motor, &mut delay)
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            encoder.run(0, 1000);
+            assert_eq!(
+                controller
+                    .update(&mut dev, &mut motor, &mut delay)
+                    .await
+                    .unwrap(),
+                stalled
+            );
+        }
+    }
+    #[async_std::test]
+    async fn position_control() {
+        use crate::position::{PositionConfig, PositionController};
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let mut plant = PlantDelay::default();
+        let mut motor = plant.motor();
+        let config = PositionConfig {
+            tolerance: 5,
+            timeout_us: 2_000_000,
+            ..Default::default()
+        };
+        let mut controller = PositionController::new(plant.encoder.clone(), config);
+
+        let position = controller
+            .move_to(2_000, &mut dev, &mut motor, &mut plant)
+            .await
+            .unwrap();
+        assert!((position - 2_000).abs() <= 5);
+        assert_eq!(plant.in1.duty(), plant.in2.duty());
+        let position = controller
+            .move_to(-500, &mut dev, &mut motor, &mut plant)
+            .await
+            .unwrap();
+        assert!((position + 500).abs() <= 5);
+
+        // Reported ITRIP regulation sets the FAULT bit, the move still completes.
+        dev.modify_cr2(|mut cr2| {
+            cr2.set_itrip_rep(true);
+            cr2
+        })
+        .await
+        .unwrap();
+        sim.set_itrip(true, true);
+        let position = controller
+            .move_to(500, &mut dev, &mut motor, &mut plant)
+            .await
+            .unwrap();
+        assert!((position - 500).abs() <= 5);
+        sim.set_itrip(false, false);
+
+        plant.stalled.set(true);
+        let start = plant.delay.elapsed_us();
+        assert!(matches!(
+            controller
+                .move_to(0, &mut dev, &mut motor, &mut plant)
+                .await,
+            Err(Drv8873Error::Timeout())
+        ));
+        assert_eq!(plant.delay.elapsed_us() - start, 2_000_000);
+        assert_eq!(motor.speed(), 0);
+
+        plant.stalled.set(false);
+        sim.inject(SimFault::Ocp(Fet::LowSide1));
+        assert!(matches!(
+            controller
+                .move_to(0, &mut dev, &mut motor, &mut plant)
+                .await,
+            Err(Drv8873Error::Drv8873Fault(_))
+        ));
+    }
+    #[async_std::test]
+    async fn current_control() {
+        use crate::current::{CurrentController, CurrentSense, IpropiScale};
+        use crate::pid::{PidGains, ONE};
+        /// IPROPI of a locked rotor that draws 10 A at full duty.
+        struct Ipropi(TestPwm, TestPwm);
+        impl CurrentSense for Ipropi {
+            fn read_millivolts(&mut self) -> u32 {
+                (self.0.duty() as i32 - self.1.duty() as i32).unsigned_abs() * 11
+            }
+        }
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let plant = PlantDelay::default();
+        let mut motor = plant.motor();
+        let gains = PidGains {
+            kp: ONE,
+            ki: 40 * ONE,
+            kd: 0,
+        };
+        let adc = Ipropi(plant.in1.clone(), plant.in2.clone());
+        let mut controller = CurrentController::new(adc, IpropiScale::default(), gains);
+        let mut delay = TestDelay::default();
+
+        let level = controller.set_setpoint(&mut dev, 3_000).await.unwrap();
+        assert_eq!(level, ITripLvl::Ampere4);
+        assert_eq!(sim.config().cr4.i_trip_lvl(), ITripLvl::Ampere4);
+        for _ in 0..2000 {
+            controller
+                .update(&mut dev, &mut motor, &mut delay, 1000)
+                .await
+                .unwrap();
+        }
+        assert!((controller.current() as i32 - 3_000).abs() <= 100);
+        assert_eq!(motor.speed(), 30);
+
+        controller.set_setpoint(&mut dev, -5_000).await.unwrap();
+        assert_eq!(sim.config().cr4.i_trip_lvl(), ITripLvl::Ampere5_4);
+        for _ in 0..2000 {
+            controller
+                .update(&mut dev, &mut motor, &mut delay, 1000)
+                .await
+                .unwrap();
+        }
+        assert!((controller.current() as i32 - 5_000).abs() <= 100);
+        assert_eq!(motor.speed(), -50);
+
+        assert!(controller.set_setpoint(&mut dev, 7_000).await.is_err());
+        assert_eq!(controller.setpoint(), -5_000);
+    }
+    #[async_std::test]
+    async fn stall_detection() {
+        use crate::encoder::{Encoder, SpeedEstimator};
+        use crate::stall::{StallAction, StallConfig, StallDetector};
+        use embedded_hal_async::delay::DelayNs;
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let mut plant = PlantDelay::default();
+        let mut motor = plant.motor();
+        let mut encoder = plant.encoder.clone();
+        let mut estimator = SpeedEstimator::new();
+        let mut detector = StallDetector::new(StallConfig {
+            window_us: 10_000,
+            min_speed: Some(1_000),
+            action: StallAction::Coast,
+            ..Default::default()
+        });
+
+        // A short ITRIP peak while running doesn't trigger it.
+        motor.set_speed(50).unwrap();
+        for i in 0..100 {
+            sim.set_itrip(i < 5, false);
+            plant.delay_us(1000).await;
+            let speed = estimator.update(encoder.sample());
+            let event = detector
+                .check(&mut dev, &mut motor, None, Some(speed), 1000)
+                .await
+                .unwrap();
+            assert!(event.is_none());
+        }
+
+        // A slow motor without a high load isn't stalled.
+        plant.stalled.set(true);
+        for _ in 0..50 {
+            plant.delay_us(1000).await;
+            let speed = estimator.update(encoder.sample());
+            let event = detector
+                .check(&mut dev, &mut motor, None, Some(speed), 1000)
+                .await
+                .unwrap();
+            assert!(event.is_none());
+        }
+
+        // Hitting the end stop stops the encoder and causes ITRIP regulation.
+        sim.set_itrip(true, false);
+        let mut events = 0;
+        for _ in 0..50 {
+            plant.delay_us(1000).await;
+            let speed = estimator.update(encoder.sample());
+            if let Some(event) = detector
+                .check(&mut dev, &mut motor, None, Some(speed), 1000)
+                .await
+                .unwrap()
+            {
+                assert_eq!(event.action, StallAction::Coast);
+                assert_eq!(event.duration_us, 10_000);
+                events += 1;
+            }
+        }
+        assert_eq!(events, 1);
+        assert!(detector.is_stalled());
+        assert_eq!((plant.in1.duty(), plant.in2.duty()), (0, 0));
+        assert_eq!(motor.speed(), 0);
+
+        // Velocities that round to zero percent still run the window.
+        detector.reset();
+        motor.set_velocity(100).unwrap();
+        assert_eq!(motor.speed(), 0);
+        let mut stalled = false;
+        for _ in 0..20 {
+            stalled |= detector
+                .check(&mut dev, &mut motor, None, Some(0), 1000)
+                .await
+                .unwrap()
+                .is_some();
+        }
+        assert!(stalled);
+    }
+    #[async_std::test]
+    async fn homing() {
+        use crate::homing::{Homing, HomingConfig};
+        use crate::stall::StallConfig;
+        use embedded_hal_async::delay::DelayNs;
+        /// Runs the plant against an end stop at count -1000 that causes ITRIP regulation.
+        struct EndStop(PlantDelay, SimDevice);
+        impl DelayNs for EndStop {
+            async fn delay_ns(&mut self, ns: u32) {
+                let PlantDelay {
+                    encoder,
+                    in1,
+                    in2,
+                    stalled,
+                    ..
+                } = &self.0;
+                let pushing = encoder.0.get().count <= -1000 && in2.duty() > in1.duty();
+                stalled.set(pushing);
+                self.1.set_itrip(pushing, pushing);
+                self.0.delay_ns(ns).await;
+            }
+        }
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let mut cfg = DRV8873Config::default();
+        cfg.cr1.set_mode(Mode::PWM);
+        dev.write_config(&cfg).await.unwrap();
+        let mut delay = EndStop(PlantDelay::default(), sim.clone());
+        let mut motor = delay.0.motor();
+        let config = HomingConfig {
+            stall: StallConfig {
+                window_us: 20_000,
+                ..Default::default()
+            },
+            back_off_us: 40_000,
+            timeout_us: 1_000_000,
+            ..Default::default()
+        };
+        let homing = Homing::new(config);
+
+        let report = homing.home(&mut dev, &mut motor, &mut delay).await.unwrap();
+        assert_eq!(report.stall.duration_us, 20_000);
+        // 2500 counts per second toward the end stop, then backing off for 40ms.
+        assert!(report.travel_us > 400_000);
+        assert!((delay.0.encoder.0.get().count + 900).abs() <= 1);
+        assert_eq!(motor.speed(), 0);
+        assert_eq!(sim.config(), cfg);
+
+        // With ITRIP reported as a fault the end stop sets the FAULT bit, homing still succeeds.
+        cfg.cr2.set_itrip_rep(true);
+        dev.write_config(&cfg).await.unwrap();
+        delay.0.encoder.0.set(encoder::EncoderSample {
+            count: 0,
+            timestamp_us: 0,
+        });
+        let report = homing.home(&mut dev, &mut motor, &mut delay).await.unwrap();
+        assert_eq!(report.stall.duration_us, 20_000);
+        assert_eq!(sim.config(), cfg);
+
+        // Without an end stop in reach homing times out, the config is restored anyway.
+        delay.0.encoder.0.set(encoder::EncoderSample {
+            count: 1_000_000,
+            timestamp_us: 0,
+        });
+        sim.set_itrip(false, false);
+        assert!(matches!(
+            homing.home(&mut dev, &mut motor, &mut delay).await,
+            Err(Drv8873Error::Timeout())
+        ));
+        assert_eq!(sim.config(), cfg);
+        assert_eq!(motor.speed(), 0);
+    }
+    #[async_std::test]
+    async fn motor_reversal() {
+        use crate::motor::ReversalPolicy;
+        use embedded_hal_async::delay::DelayNs;
+        /// Records whether the outputs were disabled while waiting, through the output disable
+        /// bits or the DISABLE pin.
+        struct OutputsDelay(TestDelay, SimDevice, TestPin, bool);
+        impl DelayNs for OutputsDelay {
+            async fn delay_ns(&mut self, ns: u32) {
+                let cr3 = self.1.config().cr3;
+                self.3 |= cr3.out1_dis() && cr3.out2_dis() || self.2.is_high();
+                self.0.delay_ns(ns).await;
+            }
+        }
+        let sim = SimDevice::new();
+        let disable = TestPin::default();
+        let mut dev: DRV8873<SimDevice, TestPin> = DRV8873::new(sim.clone());
+        let mut delay = OutputsDelay(TestDelay::default(), sim.clone(), disable.clone(), false);
+
+        let (in1, in2) = (TestPwm::default(), TestPwm::default());
+        let mut motor: Motor<TestPwm, TestPin> =
+            Motor::new(InputMode::PWM(Some((in1.clone(), in2.clone()))));
+        motor.drive(80, &mut dev, &mut delay).await.unwrap();
+        motor.drive(40, &mut dev, &mut delay).await.unwrap();
+        assert_eq!(delay.0.elapsed_us(), 0);
+        motor.drive(-40, &mut dev, &mut delay).await.unwrap();
+        assert_eq!(delay.0.elapsed_us(), 10_000);
+        assert_eq!((in1.duty(), in2.duty()), (0, 400));
+
+        motor.set_reversal(ReversalPolicy::Ramp {
+            deceleration: ramp::FULL_SCALE as u32,
+            tick_us: 1_000,
+        });
+        motor.drive(100, &mut dev, &mut delay).await.unwrap();
+        assert_eq!(delay.0.elapsed_us(), 10_000 + 400_000);
+        assert_eq!(motor.speed(), 100);
+
+        motor.set_reversal(ReversalPolicy::Coast { duration_us: 5_000 });
+        motor.drive(-10, &mut dev, &mut delay).await.unwrap();
+        assert!(!delay.3);
+        assert_eq!(delay.0.elapsed_us(), 415_000);
+
+        motor.set_reversal(ReversalPolicy::default());
+        motor
+            .drive_velocity(i16::MAX / 2, &mut dev, &mut delay)
+            .await
+            .unwrap();
+        assert_eq!(delay.0.elapsed_us(), 425_000);
+        assert_eq!((in1.duty(), in2.duty()), (499, 0));
+
+        // Independent half-bridges coast through the output disable bits.
+        let (in1, in2) = (TestPwm::default(), TestPwm::default());
+        let mut motor: Motor<TestPwm, TestPin> =
+            Motor::new(InputMode::IndependentHalfBridge(Some((in1, in2))))
+                .with_reversal(ReversalPolicy::Coast { duration_us: 5_000 });
+        motor.drive(50, &mut dev, &mut delay).await.unwrap();
+        motor.drive(-50, &mut dev, &mut delay).await.unwrap();
+        assert!(delay.3);
+        let cr3 = sim.config().cr3;
+        assert!(!cr3.out1_dis() && !cr3.out2_dis());
+
+        // In phase/enable mode EN = 0 brakes, coasting needs the DISABLE pin.
+        let (en, ph) = (TestPwm::default(), TestPin::default());
+        let mut motor: Motor<TestPwm, TestPin> =
+            Motor::new(InputMode::PhaseEnable(Some((en.clone(), ph.clone()))))
+                .with_reversal(ReversalPolicy::Coast { duration_us: 5_000 });
+        motor.drive(50, &mut dev, &mut delay).await.unwrap();
+        assert!(matches!(
+            motor.drive(-50, &mut dev, &mut delay).await,
+            Err(Drv8873Error::InputError(_))
+        ));
+        let mut dev = dev.with_disable_pin(disable.clone());
+        delay.3 = false;
+        motor.drive(50, &mut dev, &mut delay).await.unwrap();
+        motor.drive(-50, &mut dev, &mut delay).await.unwrap();
+        assert!(delay.3);
+        assert!(!disable.is_high());
+        let cr3 = sim.config().cr3;
+        assert!(!cr3.out1_dis() && !cr3.out2_dis());
+        assert_eq!((en.duty(), ph.is_high()), (500, false));
+    }
+    #[async_std::test]
+    async fn stepper() {
+        use crate::stepper::{Coil, StepMode, Stepper, StepperConfig};
+        let (sim_a, sim_b) = (SimDevice::new(), SimDevice::new());
+        let pwm: [TestPwm; 4] = Default::default();
+        let coil = |sim: &SimDevice, in1: &TestPwm, in2: &TestPwm| {
+            let dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+            let motor: Motor<TestPwm, TestPin> =
+                Motor::new(InputMode::PWM(Some((in1.clone(), in2.clone()))));
+            (dev, motor)
+        };
+        let coils = [
+            coil(&sim_a, &pwm[0], &pwm[1]),
+            coil(&sim_b, &pwm[2], &pwm[3]),
+        ];
+        let duty = || {
+            let d = |i: usize| pwm[i].duty() as i32 - pwm[i + 1].duty() as i32;
+            (d(0), d(2))
+        };
+        let mut stepper = Stepper::new(coils, StepperConfig::default());
+
+        // Full steps go around the four quadrants.
+        let mut sequence = vec![];
+        for _ in 0..4 {
+            stepper.step(true).unwrap();
+            sequence.push(duty());
+        }
+        assert_eq!(
+            sequence,
+            [(-1000, 1000), (-1000, -1000), (1000, -1000), (1000, 1000)]
+        );
+        // Half steps switch one coil off in between.
+        stepper.set_config(StepperConfig {
+            mode: StepMode::Half,
+            ..Default::default()
+        });
+        assert_eq!(stepper.position(), 8);
+        stepper.step(true).unwrap();
+        assert_eq!(duty(), (0, 1000));
+        // Microsteps follow the sine and cosine.
+        stepper.set_config(StepperConfig {
+            mode: StepMode::Eighth,
+            ..Default::default()
+        });
+        stepper.step(false).unwrap();
+        assert_eq!(duty(), (195, 980));
+        assert_eq!(StepMode::Eighth.coil_duty(4), (0, i16::MAX));
+        // The phase wraps around at the ends of the position range.
+        assert_eq!(
+            StepMode::ThirtySecond.coil_duty(i32::MAX),
+            StepMode::ThirtySecond.coil_duty(-1)
+        );
+
+        // A trapezoidal move between 100 and 1000 steps per second.
+        let mut delay = TestDelay::default();
+        stepper.set_config(StepperConfig::default());
+        let start = stepper.position();
+        let position = stepper.move_steps(-500, &mut delay).await.unwrap();
+        assert_eq!(position, start - 500);
+        // About 0.45s to accelerate and 0.45s to decelerate, covering almost the whole move.
+        assert!((900_000..950_000).contains(&delay.elapsed_us()));
+
+        sim_b.inject(SimFault::Ocp(Fet::LowSide2));
+        let error = stepper.move_steps(10, &mut delay).await.unwrap_err();
+        assert_eq!(error.coil, Coil::B);
+        assert!(matches!(error.error, Drv8873Error::Drv8873Fault(f) if f.ocp()));
+        assert_eq!(stepper.position(), position + 1);
+    }
+    #[cfg(feature = "embassy")]
+    #[async_std::test]
+    async fn driver_task() {
+        use crate::actor::{Command, DriverTask, Event};
+        use embassy_futures::select::{select, Either};
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+        use embassy_sync::channel::Channel;
+        use embassy_sync::pubsub::PubSubChannel;
+        /// Lets the other futures run once per delay.
+        #[derive(Clone)]
+        struct YieldDelay;
+        impl embedded_hal_async::delay::DelayNs for YieldDelay {
+            async fn delay_ns(&mut self, _ns: u32) {
+                embassy_futures::yield_now().await;
+            }
+        }
+        let sim = SimDevice::new();
+        let dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let plant = PlantDelay::default();
+        let task = DriverTask::new(dev, plant.motor());
+        let commands: Channel<NoopRawMutex, Command, 4> = Channel::new();
+        let events: PubSubChannel<NoopRawMutex, Event, 8, 1, 1> = PubSubChannel::new();
+        let mut subscriber = events.subscriber().unwrap();
+        let publisher = events.dyn_publisher().unwrap();
+
+        let test = async {
+            commands.send(Command::SetVelocity(i16::MAX / 2)).await;
+            assert!(
+                matches!(subscriber.next_message_pure().await, Event::Status(s) if !s.fault.fault())
+            );
+            assert_eq!(plant.in1.duty(), 499);
+
+            sim.inject(SimFault::Ocp(Fet::HighSide1));
+            assert!(matches!(subscriber.next_message_pure().await, Event::Fault(f) if f.ocp()));
+            assert!(
+                matches!(subscriber.next_message_pure().await, Event::Status(s) if s.diagnostics.ocp_h1())
+            );
+            assert!(matches!(
+                subscriber.next_message_pure().await,
+                Event::Status(_)
+            ));
+
+            commands.send(Command::Brake).await;
+            commands.send(Command::ClearFault).await;
+            commands.send(Command::ReadStatus).await;
+            assert!(matches!(
+                subscriber.next_message_pure().await,
+                Event::FaultCleared
+            ));
+            assert!(
+                matches!(subscriber.next_message_pure().await, Event::Status(s) if !s.fault.fault())
+            );
+            assert_eq!(plant.in1.duty(), plant.in2.duty());
+
+            let mut cfg = DRV8873Config::default();
+            cfg.cr4.set_i_trip_lvl(ITripLvl::Ampere4);
+            commands.send(Command::ApplyConfig(cfg.clone())).await;
+            // The status poll running when the command arrived is published first.
+            for _ in 0..2 {
+                subscriber.next_message_pure().await;
+            }
+            assert_eq!(sim.config(), cfg);
+        };
+        let run = task.run(commands.dyn_receiver(), publisher, YieldDelay);
+        assert!(matches!(select(run, test).await, Either::Second(())));
+    }
+    #[async_std::test]
+    async fn command_watchdog() {
+        use crate::watchdog::{CommandWatchdog, TimeoutAction, WatchdogConfig, WatchdogEvent};
+        use embedded_hal_async::delay::DelayNs;
+        let disable = TestPin::default();
+        let mut dev: DRV8873<SimDevice, TestPin> =
+            DRV8873::new(SimDevice::new()).with_disable_pin(disable.clone());
+        let mut plant = PlantDelay::default();
+        let mut motor = plant.motor();
+        let config = WatchdogConfig {
+            timeout_us: 100_000,
+            action: TimeoutAction::Coast,
+            assert_disable: true,
+        };
+        let mut watchdog = CommandWatchdog::new(plant.delay.clone(), config);
+
+        // Commands every 50ms keep the motor running.
+        for _ in 0..10 {
+            assert_eq!(watchdog.feed(&mut dev).unwrap(), None);
+            motor.set_speed(60).unwrap();
+            plant.delay_ms(50).await;
+            assert_eq!(watchdog.check(&mut dev, &mut motor).unwrap(), None);
+        }
+        plant.delay_ms(60).await;
+        assert_eq!(
+            watchdog.check(&mut dev, &mut motor).unwrap(),
+            Some(WatchdogEvent::CommandTimeout { silent_us: 110_000 })
+        );
+        assert_eq!((plant.in1.duty(), plant.in2.duty()), (0, 0));
+        assert!(disable.is_high());
+        plant.delay_ms(10).await;
+        assert!(matches!(
+            watchdog.check(&mut dev, &mut motor).unwrap(),
+            Some(WatchdogEvent::CommandTimeout { .. })
+        ));
+
+        assert_eq!(
+            watchdog.feed(&mut dev).unwrap(),
+            Some(WatchdogEvent::CommandsResumed)
+        );
+        assert!(!disable.is_high());
+        assert!(!watchdog.is_expired());
+
+        // Without a DISABLE pin the watchdog can't assert it.
+        let mut dev: DRV8873<SimDevice, TestPin> = DRV8873::new(SimDevice::new());
+        plant.delay_ms(200).await;
+        assert!(watchdog.check(&mut dev, &mut motor).is_err());
+    }
+    #[async_std::test]
+    async fn power_up_init() {
+        use crate::init::InitOptions;
+        let sim = SimDevice::new();
+        sim.inject(SimFault::Uvlo);
+        let n_sleep = TestPin::default();
+        let mut dev: DRV8873<SimDevice, TestPin> =
+            DRV8873::new(sim.clone()).with_n_sleep_pin(n_sleep.clone());
+        let mut delay = TestDelay::default();
+        let mut cfg = DRV8873Config::default();
+        cfg.cr1.set_mode(Mode::PhaseEnable);
+        cfg.cr4.set_i_trip_lvl(ITripLvl::Ampere4);
+        let options = InitOptions {
+            lock: true,
+            ..Default::default()
+        };
+
+        let report = dev.init(&cfg, options, &mut delay).await.unwrap();
+        assert!(report.woke && n_sleep.is_high());
+        assert_eq!(delay.elapsed_us(), 1_000);
+        assert!(report.initial_fault.uvlo());
+        assert_eq!(report.initial_config, DRV8873Config::default());
+        assert!(!sim.fault_status().fault());
+        assert_eq!(sim.config(), report.config);
+        assert_eq!(report.config.cr1.mode(), Mode::PhaseEnable);
+        assert_eq!(report.config.cr3.lock(), Lock::Locked);
+
+        // A locked device is unlocked to write the new configuration.
+        cfg.cr4.set_i_trip_lvl(ITripLvl::Ampere5_4);
+        let report = dev.init(&cfg, InitOptions::default(), &mut delay).await;
+        let report = report.unwrap();
+        assert_eq!(report.config, sim.config());
+        assert_eq!(sim.config().cr3.lock(), Lock::Unlocked);
+        assert_eq!(sim.config().cr4.i_trip_lvl(), ITripLvl::Ampere5_4);
+
+        // Without an nSLEEP pin there's nothing to wake.
+        let mut dev: DRV8873<SimDevice, TestPin> = DRV8873::new(sim.clone());
+        assert!(!dev.init(&cfg, options, &mut delay).await.unwrap().woke);
+        assert!(matches!(
+            dev.set_sleep(true),
+            Err(Drv8873Error::SleepError())
+        ));
+    }
+    #[async_std::test]
+    async fn self_test() {
+        use crate::selftest::SelfTestOptions;
+        /// nFAULT follows the simulated fault bit unless it's stuck high.
+        struct NFault(SimDevice, bool);
+        impl embedded_hal::digital::ErrorType for NFault {
+            type Error = core::convert::Infallible;
+        }
+        impl embedded_hal::digital::InputPin for NFault {
+            fn is_high(&mut self) -> Result<bool, Self::Error> {
+                Ok(self.1 || !self.0.fault_status().fault())
+            }
+            fn is_low(&mut self) -> Result<bool, Self::Error> {
+                Ok(!self.is_high()?)
+            }
+        }
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<SimDevice, PinMock> = DRV8873::new(sim.clone());
+        let mut delay = TestDelay::default();
+        let mut n_fault = NFault(sim.clone(), false);
+        let cfg = dev
+            .modify_config(|mut cfg| {
+                cfg.cr1.set_mode(Mode::PhaseEnable);
+                cfg.cr3.set_en_in1(true);
+                cfg.cr3.set_lock(Lock::Locked);
+                cfg
+            })
+            .await
+            .unwrap();
+
+        let report = dev
+            .self_test(SelfTestOptions::default(), &mut delay, &mut n_fault)
+            .await
+            .unwrap();
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(sim.config(), cfg);
+
+        // A disconnected load latches an open-load fault that clr_flt clears.
+        sim.set_open_load(false, true);
+        let report = dev
+            .self_test(SelfTestOptions::default(), &mut delay, &mut n_fault)
+            .await
+            .unwrap();
+        assert!(report.registers_ok() && report.framing && report.nfault_agrees);
+        assert!(report.open_load_done && report.open_load.ol2() && !report.open_load_ok());
+        assert!(report.latched_fault.old() && report.fault_cleared);
+        assert!(!report.passed());
+
+        // A stuck nFAULT pin disagrees with the latched fault.
+        n_fault.1 = true;
+        let report = dev
+            .self_test(SelfTestOptions::default(), &mut delay, &mut n_fault)
+            .await
+            .unwrap();
+        assert!(!report.nfault_agrees);
+        assert_eq!(sim.config(), cfg);
+
+        /// Records whether the bridge was ever driven through SPI, left PWM mode or ran with
+        /// overcurrent or charge-pump protection turned off.
+        struct Watch(SimDevice, bool);
+        impl embedded_hal_async::spi::ErrorType for Watch {
+            type Error = core::convert::Infallible;
+        }
+        impl embedded_hal_async::spi::SpiDevice for Watch {
+            async fn transaction(
+                &mut self,
+                operations: &mut [embedded_hal_async::spi::Operation<'_, u8>],
+            ) -> Result<(), Self::Error> {
+                embedded_hal_async::spi::SpiDevice::transaction(&mut self.0, operations).await?;
+                let cfg = self.0.config();
+                self.1 |= cfg.cr1.spi_in() && (cfg.cr3.en_in1() || cfg.cr3.ph_in2())
+                    || cfg.cr1.mode() != Mode::PWM
+                    || cfg.cr2.ocp_mode() == OcpMode::NoAction
+                    || cfg.cr2.dis_cpuv();
+                Ok(())
+            }
+        }
+        // Starting from SPI control of the inputs, the patterns must not drive the outputs, change
+        // the mode or turn the protection off.
+        let sim = SimDevice::new();
+        let mut dev: DRV8873<Watch, PinMock> = DRV8873::new(Watch(sim.clone(), false));
+        let cfg = dev
+            .modify_config(|mut cfg| {
+                cfg.cr1.set_spi_in(true);
+                cfg
+            })
+            .await
+            .unwrap();
+        let mut n_fault = NFault(sim.clone(), false);
+        let report = dev
+            .self_test(SelfTestOptions::default(), &mut delay, &mut n_fault)
+            .await
+            .unwrap();
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(sim.config(), cfg);
+        assert!(!dev.release().dev.1);
+    }
+    #[async_std::test]
+    async fn fault_log() {
+        use crate::faultlog::{FaultLog, FaultRecord, RecoveryAction};
+        use embedded_hal_async::delay::DelayNs;
+        let sim = SimDevice::new();
+        let mut plant = PlantDelay::default();
+        let mut motor = plant.motor();
+        let mut dev: DRV8873<SimDevice, TestPin, FaultLog<TestDelay, 2>> =
+            DRV8873::new(sim.clone()).with_fault_log(FaultLog::new(plant.delay.clone()));
+
+        motor.drive(50, &mut dev, &mut plant).await.unwrap();
+        dev.read_fault().await.unwrap();
+        assert!(dev.fault_log().is_empty());
+        plant.delay_ms(5).await;
+        sim.inject(SimFault::Ocp(Fet::HighSide1));
+        assert!(dev.read_fault().await.unwrap().ocp());
+        // Diagnostics read while the fault is reported are merged into its record.
+        dev.read_diagnostics().await.unwrap();
+        let record = *dev.fault_log().latest().unwrap();
+        assert_eq!(record.timestamp_us, 5_000);
+        assert!(record.status.fault.ocp() && record.status.diagnostics.ocp_h1());
+        assert_eq!(record.velocity, motor.velocity());
+        motor.drive(0, &mut dev, &mut plant).await.unwrap();
+        assert!(dev.fault_log_mut().set_action(RecoveryAction::Coast));
+
+        // Only changes are recorded, unless the fault was cleared in between.
+        dev.read_fault().await.unwrap();
+        assert_eq!(dev.fault_log().len(), 1);
+        dev.clear_fault().await.unwrap();
+        sim.inject(SimFault::Ocp(Fet::HighSide1));
+        dev.read_fault().await.unwrap();
+        let recurred = *dev.fault_log().latest().unwrap();
+        assert_eq!(recurred.status.fault.0, record.status.fault.0);
+        assert_eq!(recurred.velocity, 0);
+        dev.clear_fault().await.unwrap();
+        assert_eq!(
+            dev.fault_log().latest().unwrap().action,
+            RecoveryAction::ClearFault
+        );
+        assert_eq!(
+            dev.fault_log().iter().next().unwrap().action,
+            RecoveryAction::Coast
+        );
+
+        // ITRIP current regulation toggles the FAULT bit with itrip_rep, it isn't an event.
+        dev.modify_cr2(|mut cr2| {
+            cr2.set_itrip_rep(true);
+            cr2
+        })
+        .await
+        .unwrap();
+        for active in [true, false, true, false] {
+            sim.set_itrip(active, false);
+            assert_eq!(dev.read_fault().await.unwrap().fault(), active);
+            dev.read_diagnostics().await.unwrap();
+        }
+        assert_eq!(dev.fault_log().total(), 2);
+
+        sim.inject(SimFault::Otw);
+        sim.inject(SimFault::Uvlo);
+        plant.delay_ms(5).await;
+        dev.read_fault().await.unwrap();
+        assert_eq!(dev.fault_log().total(), 3);
+        let status = crate::array::DeviceStatus {
+            fault: sim.fault_status(),
+            diagnostics: sim.diagnostic_status(),
+        };
+        let log = dev.fault_log_mut();
+        log.record(status, 0, RecoveryAction::ClearFault);
+
+        // The oldest record was overwritten.
+        assert_eq!((log.len(), log.total()), (2, 4));
+        let uvlo = log.iter().next().unwrap();
+        assert!(uvlo.status.fault.uvlo() && uvlo.velocity == 0);
+        assert_eq!(log.latest().unwrap().action, RecoveryAction::ClearFault);
+
+        let mut buf = [0; 3 * FaultRecord::SIZE];
+        assert_eq!(log.export(&mut buf[..20]), FaultRecord::SIZE);
+        assert_eq!(log.export(&mut buf), 2 * FaultRecord::SIZE);
+        for (chunk, record) in buf.chunks_exact(FaultRecord::SIZE).zip(log.iter()) {
+            let decoded = FaultRecord::from_bytes(chunk.try_into().unwrap());
+            assert_eq!(decoded.to_bytes(), record.to_bytes());
+            assert_eq!(decoded.timestamp_us, 10_000);
+        }
+        log.clear();
+        assert!(log.is_empty() && !log.set_action(RecoveryAction::Coast));
+    }
+    #[test]
+    fn safe_shutdown() {
+        use crate::SafeState;
+        use embedded_hal::digital::OutputPin;
+        let (disable, n_sleep) = (TestPin::default(), TestPin::default());
+        let mut dev: DRV8873<SimDevice, TestPin> = DRV8873::new(SimDevice::new())
+            .with_disable_pin(disable.clone())
+            .with_n_sleep_pin(n_sleep.clone());
+        dev.set_sleep(false).unwrap();
+        assert!(n_sleep.is_high() && !disable.is_high());
+        drop(dev);
+        // By default the device stays awake and keeps its configuration.
+        assert!(disable.is_high() && n_sleep.is_high());
+
+        // Release applies the safe state and returns the pins.
+        let dev: DRV8873<SimDevice, TestPin> = DRV8873::new(SimDevice::new())
+            .with_disable_pin(disable.clone())
+            .with_n_sleep_pin(n_sleep.clone())
+            .with_safe_state(SafeState {
+                disable: true,
+                sleep: true,
+            });
+        let mut parts = dev.release();
+        assert!(disable.is_high() && !n_sleep.is_high());
+        let (mut pin_disable, mut pin_sleep) = (
+            parts.disable_pin.take().unwrap(),
+            parts.n_sleep_pin.take().unwrap(),
+        );
+        pin_disable.set_low().unwrap();
+        pin_sleep.set_high().unwrap();
+        drop(parts);
+        assert!(!disable.is_high() && n_sleep.is_high());
+
+        // Dropping a motor coasts its inputs, releasing it leaves them as they are.
+        let plant = PlantDelay::default();
+        let mut motor = plant.motor();
+        motor.set_speed(50).unwrap();
+        drop(motor);
+        assert_eq!((plant.in1.duty(), plant.in2.duty()), (0, 0));
+        let mut motor = plant.motor();
+        motor.set_speed(50).unwrap();
+        let duty = plant.in1.duty();
+        let _input = motor.into_inner();
+        assert_eq!(plant.in1.duty(), duty);
+        assert!(duty > 0);
+    }
+    #[test]
+    fn status_byte() {
+        let mut sim = SimDevice::new();
+        let mut buf = [CommandByte::read(0x02).0, 0x00];
+        BlockingSpiDevice::transfer_in_place(&mut sim, &mut buf).unwrap();
+        assert_eq!(buf, [0b1100_0000, ControlRegister1::default().0]);
+        assert!(registers::get_status(buf[0]).is_none());
+
+        sim.inject(SimFault::Uvlo);
+        let mut buf = [CommandByte::write(0x02).0, 0x00];
+        BlockingSpiDevice::transfer_in_place(&mut sim, &mut buf).unwrap();
+        assert!(registers::get_status(buf[0]).unwrap().uvlo());
+        // Writes return the register contents from before the write.
+        assert_eq!(buf[1], ControlRegister1::default().0);
+        assert_eq!(sim.register(0x02), 0x00);
+        assert_eq!(sim.frames(), 2);
+    }
+}
+#[test]
+fn output_truth_table() {
+    use outputs::{BridgeInputs, OutputState::*};
+    let inputs = |mode, in1, in2| BridgeInputs {
+        mode,
+        spi_in: false,
+        in1,
+        in2,
+        en_in1: false,
+        ph_in2: false,
+        out1_dis: false,
+        out2_dis: false,
+        disable: false,
+        n_sleep: true,
+    };
+    assert_eq!(inputs(Mode::PhaseEnable, false, true).outputs(), (Low, Low));
+    assert_eq!(
+        inputs(Mode::PhaseEnable, true, false).outputs(),
+        (Low, High)
+    );
+    assert_eq!(inputs(Mode::PhaseEnable, true, true).outputs(), (High, Low));
+    assert_eq!(inputs(Mode::PWM, false, false).outputs(), (HiZ, HiZ));
+    assert_eq!(inputs(Mode::PWM, false, true).outputs(), (Low, High));
+    assert_eq!(inputs(Mode::PWM, true, false).outputs(), (High, Low));
+    assert_eq!(inputs(Mode::PWM, true, true).outputs(), (Low, Low));
+    assert_eq!(
+        inputs(Mode::IndependentHalfBridge, true, false).outputs(),
+        (High, Low)
+    );
+    assert_eq!(
+        inputs(Mode::InputDisabled, true, false).outputs(),
+        (HiZ, HiZ)
+    );
+
+    let mut half_bridge = inputs(Mode::IndependentHalfBridge, true, true);
+    half_bridge.out2_dis = true;
+    assert_eq!(half_bridge.outputs(), (High, HiZ));
+
+    // The SPI bits replace the input pins.
+    let mut cfg = DRV8873Config::default();
+    cfg.cr1.set_spi_in(true);
+    cfg.cr3.set_en_in1(true);
+    assert_eq!(
+        BridgeInputs::from_config(&cfg, false, true, false, true).outputs(),
+        (High, Low)
+    );
+
+    // Sleep and DISABLE override everything in every mode.
+    for mode in 0..4u8 {
+        for pins in 0..4u8 {
+            let mut i = inputs(Mode::from(mode), pins & 1 != 0, pins & 2 != 0);
+            i.disable = true;
+            assert_eq!(i.outputs(), (HiZ, HiZ));
+            i.disable = false;
+            i.n_sleep = false;
+            assert_eq!(i.outputs(), (HiZ, HiZ));
+        }
+    }
+}
+#[test]
+fn input_mode() {
+    let (en, ph) = (TestPwm::default(), TestPin::default());
+    let mut input: InputMode<TestPwm, TestPin> =
+        InputMode::PhaseEnable(Some((en.clone(), ph.clone())));
+    input.forward_with_speed(40).unwrap();
+    assert!(ph.is_high());
+    assert_eq!(en.duty(), 400);
+    input.backward_with_speed(60).unwrap();
+    assert!(!ph.is_high());
+    assert_eq!(en.duty(), 600);
+    input.brake().unwrap();
+    assert_eq!(en.duty(), 0);
+
+    let (in1, in2) = (TestPwm::default(), TestPwm::default());
+    let mut input: InputMode<TestPwm, TestPin> = InputMode::PWM(Some((in1.clone(), in2.clone())));
+    input.backward_with_speed(25).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (0, 250));
+    input.brake().unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (TestPwm::MAX, TestPwm::MAX));
+
+    let mut input: InputMode<TestPwm, TestPin> = InputMode::InputDisabled;
+    assert!(input.forward_with_speed(10).is_err());
+}
+#[test]
+fn decay_modes() {
+    use inputs::Decay;
+    use outputs::{BridgeInputs, OutputState::*};
+    let mut cfg = DRV8873Config::default();
+    cfg.cr1.set_mode(Mode::PWM);
+    // The outputs at the start and at the end of an edge aligned PWM period.
+    let phases = |in1: &TestPwm, in2: &TestPwm| {
+        [0, TestPwm::MAX - 1].map(|t| {
+            BridgeInputs::from_config(&cfg, in1.duty() > t, in2.duty() > t, false, true).outputs()
+        })
+    };
+
+    let (in1, in2) = (TestPwm::default(), TestPwm::default());
+    let mut input: InputMode<TestPwm, TestPin> = InputMode::PWM(Some((in1.clone(), in2.clone())));
+    input.drive(true, 300, 1000, Decay::Slow).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (1000, 700));
+    assert_eq!(phases(&in1, &in2), [(Low, Low), (High, Low)]);
+    input.drive(false, 300, 1000, Decay::Slow).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (700, 1000));
+    assert_eq!(phases(&in1, &in2), [(Low, Low), (Low, High)]);
+    input.drive(true, 300, 1000, Decay::Fast).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (300, 0));
+    assert_eq!(phases(&in1, &in2), [(High, Low), (HiZ, HiZ)]);
+    input.drive(false, 300, 1000, Decay::Fast).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (0, 300));
+    assert_eq!(phases(&in1, &in2), [(Low, High), (HiZ, HiZ)]);
+
+    let (en, ph) = (TestPwm::default(), TestPin::default());
+    let mut input: InputMode<TestPwm, TestPin> =
+        InputMode::PhaseEnable(Some((en.clone(), ph.clone())));
+    input.drive(false, 1, 4, Decay::Slow).unwrap();
+    assert_eq!((en.duty(), ph.is_high()), (250, false));
+    assert!(input.drive(true, 1, 4, Decay::Fast).is_err());
+
+    // The motor picks the decay mode of its input mode unless it's set.
+    let mut motor: Motor<TestPwm, TestPin> =
+        Motor::new(InputMode::PWM(Some((in1.clone(), in2.clone())))).with_decay(Decay::Slow);
+    motor.set_speed(-40).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (600, 1000));
+}
+#[test]
+fn thermal_estimate() {
+    use inputs::Decay;
+    use thermal::{losses, DeviceParameters, OperatingPoint, ThermalModel};
+    let params = DeviceParameters::default();
+    let mut cfg = DRV8873Config::default();
+    cfg.cr1.set_sr(RiseTime::VoltPerUs53_2);
+    let point = OperatingPoint {
+        current_ma: 3_000,
+        supply_mv: 24_000,
+        pwm_hz: 20_000,
+        duty: 1 << 14,
+        decay: Decay::Slow,
+        rise_time: RiseTime::VoltPerUs10_8,
+    }
+    .with_config(&cfg);
+    // 451ns transitions, 3A through 150mΩ and 5mA from the supply.
+    let loss = losses(&params, &point);
+    assert_eq!(thermal::transition_ns(24_000, point.rise_time), 451);
+    assert_eq!((loss.switching_mw, loss.conduction_mw), (649, 1_350));
+    assert_eq!(loss.total_mw(), 2_119);
+    assert_eq!(
+        thermal::junction_temperature_mc(&params, loss.total_mw(), 25_000),
+        88_570
+    );
+    // Slower edges dissipate more, fast decay through the body diodes as well.
+    let slow_edges = OperatingPoint {
+        rise_time: RiseTime::VoltPerUs10_8,
+        ..point
+    };
+    assert_eq!(losses(&params, &slow_edges).switching_mw, 3_199);
+    let fast_decay = OperatingPoint {
+        decay: Decay::Fast,
+        ..point
+    };
+    assert_eq!(losses(&params, &fast_decay).conduction_mw, 675 + 2_399);
+    let full_on = OperatingPoint {
+        duty: i16::MAX as u16,
+        ..point
+    };
+    assert_eq!(losses(&params, &full_on).switching_mw, 0);
+
+    let mut model = ThermalModel::new(params, 25_000, 1_000_000);
+    model.update(&point, 1_000_000);
+    assert_eq!(model.temperature_mc(), 25_000 + 63_570 / 2);
+    for _ in 0..100 {
+        model.update(&point, 100_000);
+    }
+    assert!((model.temperature_mc() - 88_570).abs() < 100);
+
+    let mut model = ThermalModel::new(params, 25_000, 0);
+    assert_eq!(model.update(&point, 0), 25_000);
+    assert_eq!(model.update(&point, 1), 88_570);
+}
+#[test]
+fn linear_ramp() {
+    use ramp::{Profile, Ramp, RampConfig, FULL_SCALE};
+    let mut ramp = Ramp::new(RampConfig {
+        acceleration: FULL_SCALE as u32,
+        deceleration: 2 * FULL_SCALE as u32,
+        profile: Profile::Linear,
+    });
+    ramp.set_target(i16::MAX);
+    for _ in 0..500 {
+        ramp.step(1000);
+    }
+    assert!((ramp.current() as i32 - FULL_SCALE / 2).abs() <= 1);
+
+    // Reverse at half speed: decelerate to zero in 250ms, then accelerate backward.
+    ramp.set_target(-i16::MAX);
+    let mut last = ramp.current();
+    let mut crossed_zero = false;
+    for _ in 0..250 {
+        let speed = ramp.step(1000);
+        assert!(speed <= last);
+        crossed_zero |= speed == 0;
+        last = speed;
+    }
+    assert!(crossed_zero);
+    for _ in 0..2000 {
+        ramp.step(1000);
+    }
+    assert!(ramp.is_done());
+    assert_eq!(ramp.current(), -i16::MAX);
+
+    // Retarget mid-ramp and cancel.
+    ramp.set_target(0);
+    ramp.step(100_000);
+    assert!(ramp.current() < 0);
+    ramp.cancel();
+    let held = ramp.current();
+    ramp.step(100_000);
+    assert_eq!(ramp.current(), held);
+    assert!(ramp.is_done());
+
+    // A zero limit doesn't limit, the speed jumps to the target, through zero when reversing.
+    let mut ramp = Ramp::new(RampConfig {
+        acceleration: 0,
+        deceleration: FULL_SCALE as u32,
+        profile: Profile::Linear,
+    });
+    ramp.set_target(1_000);
+    assert_eq!(ramp.step(1), 1_000);
+    ramp.set_config(RampConfig {
+        acceleration: FULL_SCALE as u32,
+        deceleration: 0,
+        profile: Profile::Linear,
+    });
+    ramp.set_target(-1_000);
+    assert_eq!(ramp.step(1), 0);
+    assert_eq!(ramp.step(1_000), -32);
+}
+#[test]
+fn s_curve_ramp() {
+    use ramp::{Profile, Ramp, RampConfig, FULL_SCALE};
+    let mut ramp = Ramp::new(RampConfig {
+        acceleration: 2 * FULL_SCALE as u32,
+        deceleration: 2 * FULL_SCALE as u32,
+        profile: Profile::SCurve {
+            jerk: 20 * FULL_SCALE as u32,
+        },
+    });
+    ramp.set_target(10_000);
+    let mut speeds = vec![0i16];
+    while !ramp.is_done() && speeds.len() < 10_000 {
+        speeds.push(ramp.step(1000));
+    }
+    assert!(ramp.is_done());
+    let steps: Vec<i32> = speeds.windows(2).map(|w| (w[1] - w[0]) as i32).collect();
+    // Never overshoots, and the acceleration changes gradually at both ends.
+    assert!(speeds.iter().all(|s| (0..=10_000).contains(s)));
+    assert!(steps
+        .iter()
+        .all(|d| *d >= 0 && *d <= 2 * FULL_SCALE / 1000 + 1));
+    assert!(steps[1] < steps[steps.len() / 2]);
+    assert!(steps[steps.len() - 2] < steps[steps.len() / 2]);
+
+    // The largest limits and steps the configuration accepts don't overflow.
+    let mut ramp = Ramp::new(RampConfig {
+        acceleration: u32::MAX,
+        deceleration: u32::MAX,
+        profile: Profile::SCurve { jerk: u32::MAX },
+    });
+    ramp.set_target(i16::MAX);
+    assert_eq!(ramp.step(u32::MAX), i16::MAX);
+    ramp.set_target(-i16::MAX);
+    ramp.step(u32::MAX);
+    assert_eq!(ramp.step(u32::MAX), -i16::MAX);
+
+    // Without a jerk limit the S-curve has the linear profile.
+    let mut ramp = Ramp::new(RampConfig {
+        acceleration: FULL_SCALE as u32,
+        deceleration: FULL_SCALE as u32,
+        profile: Profile::SCurve { jerk: 0 },
+    });
+    ramp.set_target(i16::MAX);
+    assert_eq!(ramp.step(500_000), FULL_SCALE as i16 / 2);
+    for _ in 0..1_000 {
+        ramp.step(1_000);
+    }
+    assert!(ramp.is_done());
+    // Nor does a zero acceleration limit it.
+    let mut ramp = Ramp::new(RampConfig {
+        acceleration: 0,
+        deceleration: 0,
+        profile: Profile::SCurve { jerk: 0 },
+    });
+    ramp.set_target(-i16::MAX);
+    assert_eq!(ramp.step(1), -i16::MAX);
+}
+#[async_std::test]
+async fn motor_run_ramp() {
+    use ramp::{Ramp, RampConfig};
+    let (in1, in2) = (TestPwm::default(), TestPwm::default());
+    let mut motor: Motor<TestPwm, TestPin> =
+        Motor::new(InputMode::PWM(Some((in1.clone(), in2.clone()))));
+    let mut dev: DRV8873<sim::SimDevice, TestPin> = DRV8873::new(sim::SimDevice::new());
+    let mut ramp = Ramp::new(RampConfig::default());
+    let mut delay = TestDelay::default();
+    ramp.set_target(-i16::MAX / 2);
+    motor
+        .run_ramp(&mut ramp, &mut dev, &mut delay, 1000)
+        .await
+        .unwrap();
+    assert_eq!(motor.speed(), -50);
+    // 16383 / 32767 of full duty.
+    assert_eq!((in1.duty(), in2.duty()), (0, 499));
+    assert!((250_000..=252_000).contains(&delay.elapsed_us()));
+
+    // Ramping to the other direction brakes for 10ms at zero.
+    ramp.set_target(i16::MAX / 2);
+    motor
+        .run_ramp(&mut ramp, &mut dev, &mut delay, 1000)
+        .await
+        .unwrap();
+    assert_eq!(motor.speed(), 50);
+    assert!((760_000..=764_000).contains(&delay.elapsed_us()));
+}
+#[test]
+fn motor_velocity() {
+    let (in1, in2) = (TestPwm::default(), TestPwm::default());
+    let mut motor: Motor<TestPwm, TestPin> =
+        Motor::new(InputMode::PWM(Some((in1.clone(), in2.clone()))));
+    // Below one percent of full duty.
+    motor.set_velocity(33).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (1, 0));
+    assert_eq!(motor.speed(), 0);
+    // Reversing a running motor needs Motor::drive_velocity unless the policy allows it.
+    assert!(matches!(
+        motor.set_velocity(i16::MIN),
+        Err(Drv8873Error::InputError(_))
+    ));
+    assert_eq!(motor.velocity(), 33);
+    motor.set_reversal(motor::ReversalPolicy::Immediate);
+    motor.set_velocity(i16::MIN).unwrap();
+    assert_eq!((in1.duty(), in2.duty()), (0, TestPwm::MAX));
+    assert_eq!(motor.velocity(), -i16::MAX);
+    motor.set_speed(25).unwrap();
+    assert_eq!(motor.velocity(), 8191);
+    assert_eq!(motor.speed(), 25);
+}
+#[test]
+fn pid_anti_windup() {
+    use pid::{Pid, PidGains, ONE};
+    let gains = PidGains {
+        kp: 0,
+        ki: ONE,
+        kd: 0,
+    };
+    let mut pid = Pid::new(gains, -100, 100);
+    // One unit of error for a second integrates to one.
+    assert_eq!(pid.update(1, 1_000_000, false), 1);
+    assert_eq!(pid.update(1, 1_000_000, true), 1);
+    // Saturated for a long time, the integral stops at the limit.
+    for _ in 0..1000 {
+        assert!(pid.update(10, 1_000_000, false) <= 100);
+    }
+    assert_eq!(pid.update(-1, 1_000_000, false), 99);
+}
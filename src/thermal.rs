@@ -0,0 +1,172 @@
+//! Power dissipation and junction temperature estimates.
+//!
+//! The losses of the H-bridge are split into conduction losses in the FETs (or the body diodes
+//! during fast decay), switching losses while the outputs slew at the [RiseTime] set in
+//! [ControlRegister1] and the quiescent supply current. [losses] gives the steady state losses
+//! of an operating point, [ThermalModel] tracks the junction temperature at runtime with a first
+//! order thermal model.
+//!
+//! All values are integers in milli-units: mA, mV, mΩ, mW and m°C.
+//!
+//! [ControlRegister1]: crate::registers::ControlRegister1
+use crate::inputs::Decay;
+use crate::ramp::FULL_SCALE;
+use crate::registers::RiseTime;
+use crate::DRV8873Config;
+
+/// Datasheet and board parameters of the device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceParameters {
+    /// On resistance of the high-side and low-side FET in series, in mΩ. It rises with the
+    /// junction temperature, use the value at the expected temperature.
+    pub rds_on_mohm: u32,
+    /// Forward voltage of a FET body diode in mV.
+    pub diode_mv: u32,
+    /// Supply current of the device while active in µA.
+    pub supply_ua: u32,
+    /// Junction to ambient thermal resistance in m°C per W.
+    pub theta_ja_mc_per_w: u32,
+}
+
+impl Default for DeviceParameters {
+    /// Typical values at 25°C, the thermal resistance depends heavily on the board layout.
+    fn default() -> Self {
+        Self {
+            rds_on_mohm: 150,
+            diode_mv: 800,
+            supply_ua: 5_000,
+            theta_ja_mc_per_w: 30_000,
+        }
+    }
+}
+
+/// The conditions the H-bridge operates at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperatingPoint {
+    /// Load current in mA.
+    pub current_ma: u32,
+    /// Supply voltage VM in mV.
+    pub supply_mv: u32,
+    /// PWM frequency in Hz.
+    pub pwm_hz: u32,
+    /// On-time as a Q15 fraction of the PWM period.
+    pub duty: u16,
+    /// The current path during the off-time.
+    pub decay: Decay,
+    pub rise_time: RiseTime,
+}
+
+impl OperatingPoint {
+    /// Take the [RiseTime] from a device configuration.
+    pub fn with_config(mut self, cfg: &DRV8873Config) -> Self {
+        self.rise_time = cfg.cr1.sr();
+        self
+    }
+}
+
+/// Power dissipation of the device in mW.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Losses {
+    pub conduction_mw: u32,
+    pub switching_mw: u32,
+    pub quiescent_mw: u32,
+}
+
+impl Losses {
+    pub fn total_mw(&self) -> u32 {
+        self.conduction_mw + self.switching_mw + self.quiescent_mw
+    }
+}
+
+/// The time the outputs take to slew across the supply voltage in ns.
+pub fn transition_ns(supply_mv: u32, rise_time: RiseTime) -> u32 {
+    (supply_mv as u64 * 1_000 / rise_time.millivolts_per_us() as u64) as u32
+}
+
+/// Estimate the steady state losses of an operating point.
+pub fn losses(params: &DeviceParameters, point: &OperatingPoint) -> Losses {
+    let current = point.current_ma as u64;
+    let on = (point.duty as u64).min(FULL_SCALE as u64);
+    let off = FULL_SCALE as u64 - on;
+
+    // The current flows through a high-side and a low-side FET during the on-time. During the
+    // off-time it recirculates through both low-side FETs or both body diodes.
+    let fet_mw = current * current * params.rds_on_mohm as u64 / 1_000_000;
+    let off_mw = match point.decay {
+        Decay::Slow => fet_mw,
+        Decay::Fast => 2 * params.diode_mv as u64 * current / 1_000,
+    };
+    let conduction_mw = (fet_mw * on + off_mw * off) / FULL_SCALE as u64;
+
+    // Every period has a rising and a falling edge, each dissipating half the product of
+    // voltage and current over the transition.
+    let switching_mw = if on == 0 || off == 0 {
+        0
+    } else {
+        point.supply_mv as u64
+            * current
+            * transition_ns(point.supply_mv, point.rise_time) as u64
+            * point.pwm_hz as u64
+            / 1_000_000_000_000
+    };
+
+    Losses {
+        conduction_mw: conduction_mw as u32,
+        switching_mw: switching_mw as u32,
+        quiescent_mw: (point.supply_mv as u64 * params.supply_ua as u64 / 1_000_000) as u32,
+    }
+}
+
+/// The steady state junction temperature in m°C for a power dissipation.
+pub fn junction_temperature_mc(params: &DeviceParameters, power_mw: u32, ambient_mc: i32) -> i32 {
+    let rise = power_mw as i64 * params.theta_ja_mc_per_w as i64 / 1_000;
+    (ambient_mc as i64 + rise).clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+/// Tracks the junction temperature of a running device.
+///
+/// The junction approaches the steady state temperature of the current losses exponentially
+/// with the thermal time constant of the package and board.
+#[derive(Debug, Clone)]
+pub struct ThermalModel {
+    params: DeviceParameters,
+    ambient_mc: i32,
+    time_constant_us: u32,
+    temperature_mc: i32,
+}
+
+impl ThermalModel {
+    /// Start a model at ambient temperature.
+    pub fn new(params: DeviceParameters, ambient_mc: i32, time_constant_us: u32) -> Self {
+        Self {
+            params,
+            ambient_mc,
+            time_constant_us,
+            temperature_mc: ambient_mc,
+        }
+    }
+    pub fn params(&self) -> &DeviceParameters {
+        &self.params
+    }
+    /// The estimated junction temperature in m°C.
+    pub fn temperature_mc(&self) -> i32 {
+        self.temperature_mc
+    }
+    pub fn set_ambient_mc(&mut self, ambient_mc: i32) {
+        self.ambient_mc = ambient_mc;
+    }
+    /// Advance the model by `dt_us` microseconds at an operating point and return the new
+    /// junction temperature in m°C. A zero time constant follows the losses without delay.
+    pub fn update(&mut self, point: &OperatingPoint, dt_us: u32) -> i32 {
+        if dt_us == 0 {
+            return self.temperature_mc;
+        }
+        let power = losses(&self.params, point).total_mw();
+        let target = junction_temperature_mc(&self.params, power, self.ambient_mc) as i64;
+        let current = self.temperature_mc as i64;
+        let dt = dt_us as i64;
+        self.temperature_mc =
+            (current + (target - current) * dt / (self.time_constant_us as i64 + dt)) as i32;
+        self.temperature_mc
+    }
+}
@@ -0,0 +1,135 @@
+//! Command watchdog for the motor-control layer.
+//!
+//! If the controller that sends speed commands stops, for example because it crashed or lost its
+//! connection, the motor would keep running at the last duty cycle. A [CommandWatchdog] is fed
+//! with every command and stops the motor when no command arrived within the timeout. It keeps
+//! reporting [WatchdogEvent::CommandTimeout] until the commands resume.
+use embedded_hal::digital::StatefulOutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::clock::Clock;
+use crate::faultlog::FaultSink;
+use crate::motor::Motor;
+use crate::{Drv8873Error, DRV8873};
+
+/// How the motor is stopped when the watchdog expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeoutAction {
+    #[default]
+    Brake,
+    /// Turn the inputs off, see [Motor::coast].
+    Coast,
+}
+
+/// Timeout and action of a [CommandWatchdog].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// The longest time between two commands in microseconds.
+    pub timeout_us: u64,
+    pub action: TimeoutAction,
+    /// Also drive the DISABLE pin high while expired, so the outputs are Hi-Z whatever the
+    /// inputs do. Requires a pin assigned with [DRV8873::with_disable_pin].
+    pub assert_disable: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            timeout_us: 500_000,
+            action: TimeoutAction::Brake,
+            assert_disable: false,
+        }
+    }
+}
+
+/// Reported by a [CommandWatchdog].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogEvent {
+    /// No command arrived for `silent_us` microseconds, the motor is stopped.
+    CommandTimeout { silent_us: u64 },
+    /// A command arrived after a timeout.
+    CommandsResumed,
+}
+
+/// Stops a motor when it's no longer commanded.
+#[derive(Debug, Clone)]
+pub struct CommandWatchdog<C: Clock> {
+    clock: C,
+    config: WatchdogConfig,
+    last_command: u64,
+    expired: bool,
+}
+
+impl<C: Clock> CommandWatchdog<C> {
+    /// Create a watchdog, the timeout starts running right away.
+    pub fn new(mut clock: C, config: WatchdogConfig) -> Self {
+        Self {
+            last_command: clock.now_us(),
+            clock,
+            config,
+            expired: false,
+        }
+    }
+    /// Release the clock.
+    pub fn into_inner(self) -> C {
+        self.clock
+    }
+    pub fn config(&self) -> &WatchdogConfig {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: WatchdogConfig) {
+        self.config = config;
+    }
+    /// Whether the watchdog expired and no command arrived since.
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+    /// Restart the timeout, call this for every command. After a timeout the DISABLE pin is
+    /// released again if it was asserted, apply the command afterwards.
+    pub fn feed<D: SpiDevice, P: StatefulOutputPin, L: FaultSink>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+    ) -> Result<Option<WatchdogEvent>, Drv8873Error> {
+        self.last_command = self.clock.now_us();
+        if !self.expired {
+            return Ok(None);
+        }
+        if self.config.assert_disable {
+            drv.set_disabled(false)?;
+        }
+        self.expired = false;
+        Ok(Some(WatchdogEvent::CommandsResumed))
+    }
+    /// Check the timeout, call this periodically. The motor is stopped once when the watchdog
+    /// expires, after that every check reports the timeout until the next
+    /// [CommandWatchdog::feed].
+    pub fn check<D, P, PW, MP, L>(
+        &mut self,
+        drv: &mut DRV8873<D, P, L>,
+        motor: &mut Motor<PW, MP>,
+    ) -> Result<Option<WatchdogEvent>, Drv8873Error>
+    where
+        D: SpiDevice,
+        P: StatefulOutputPin,
+        PW: SetDutyCycle,
+        MP: StatefulOutputPin,
+        L: FaultSink,
+    {
+        let silent_us = self.clock.now_us().saturating_sub(self.last_command);
+        if silent_us < self.config.timeout_us {
+            return Ok(None);
+        }
+        if !self.expired {
+            match self.config.action {
+                TimeoutAction::Brake => motor.brake()?,
+                TimeoutAction::Coast => motor.coast()?,
+            }
+            if self.config.assert_disable {
+                drv.set_disabled(true)?;
+            }
+            self.expired = true;
+        }
+        Ok(Some(WatchdogEvent::CommandTimeout { silent_us }))
+    }
+}